@@ -0,0 +1,42 @@
+//! Measures decoding of small integers, which are encoded inline in their initial byte.
+//!
+//! There is no benchmark harness on stable Rust, run with `cargo bench` to print the times.
+
+extern crate serde_cbor;
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+const ROUNDS: u32 = 20;
+
+fn measure<F: FnMut() -> usize>(name: &str, mut f: F) {
+    // Warm up the allocator and caches first.
+    f();
+    let mut items = 0;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        items += f();
+    }
+    let elapsed = start.elapsed() / ROUNDS;
+    println!("{}: {:?} per round, {} items", name, elapsed, items / ROUNDS as usize);
+}
+
+fn main() {
+    // An array of 500000 integers below 24.
+    let mut array = vec![0x9a, 0x00, 0x07, 0xa1, 0x20];
+    array.extend((0..500000).map(|n| (n % 24) as u8));
+    measure("array of small integers", || {
+        serde_cbor::from_slice::<Vec<u32>>(&array).unwrap().len()
+    });
+
+    // A map of 100000 entries with 32 bit keys and small integer values.
+    let mut map = vec![0xba, 0x00, 0x01, 0x86, 0xa0];
+    for n in 0..100000u32 {
+        map.push(0x1a);
+        map.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+        map.push((n % 24) as u8);
+    }
+    measure("map of small integers", || {
+        serde_cbor::from_slice::<HashMap<u32, u8>>(&map).unwrap().len()
+    });
+}
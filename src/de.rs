@@ -1,43 +1,471 @@
 //! CBOR deserialization.
 
+use std::any::Any;
+use std::cmp;
+use std::collections::HashMap;
 use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+use std::str::FromStr;
 
 use byteorder::{BigEndian, ReadBytesExt};
 use serde::de::{self, EnumVisitor, Visitor, Deserialize};
+use serde::de::impls::IgnoredAny;
+use serde::de::value::{SeqDeserializer, ValueDeserializer};
 use serde::bytes::ByteBuf;
 
 use super::error::{Error, Result};
+use super::ser;
+use super::value::{Annotation, Value, RAW_VALUE_NAME, TAG_NAME};
 
 const MAX_SEQ_LEN: u64 = 524288;
+const POSITIVE_BIGNUM_TAG: u64 = 2;
+const NEGATIVE_BIGNUM_TAG: u64 = 3;
+const RATIONAL_TAG: u64 = 30;
+const DAYS_TAG: u64 = 100;
+const SELF_DESCRIBE_TAG: u64 = 55799;
+
+/// What the deserializer does with a tag that directly repeats the tag before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateTags {
+    /// Keeps all tags, this is the default.
+    Preserve,
+    /// Treats consecutive identical tags as a single tag.
+    Collapse,
+    /// Rejects consecutive identical tags with `Error::DuplicateTag`.
+    Reject,
+}
+
+/// How `Deserializer::deserialize_enum` treats a tag in place of an enum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VariantTags {
+    /// Rejects the tag, this is the default.
+    Reject,
+    /// Takes the tag number as the variant index and the items of the array following the tag as
+    /// the payload, for example `[]` for a unit variant or `[x]` for a newtype variant.
+    Array,
+    /// Takes the tag number as the variant index and the value following the tag as the payload.
+    /// A unit variant has null as its payload.
+    Inline,
+}
+
+type ProgressCallback = FnMut(usize) -> ControlFlow<()>;
+type TraceCallback = FnMut(&Header);
+
+/// A handler for the values of a tag, see `Deserializer::register_tag_handler`.
+pub type TagHandler = Fn(u64, &mut Deserializer<&[u8]>) -> Result<Value>;
+
+/// The initial byte of a data item, passed to the callback set with `Deserializer::trace`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// The position of the byte in the input.
+    pub offset: usize,
+    /// The number of arrays, maps and tags the item is nested in.
+    pub depth: usize,
+    /// The major type, the upper three bits.
+    pub major: u8,
+    /// The additional information, the lower five bits.
+    pub info: u8,
+}
 
 /// A structure that deserializes CBOR into Rust values.
 pub struct Deserializer<R: Read> {
     reader: R,
     first: Option<u8>,
+    padding: Option<u8>,
+    lenient_numbers: bool,
+    lenient_bools: bool,
+    duplicate_tags: DuplicateTags,
+    variant_tags: VariantTags,
+    unknown_simple_as_null: bool,
+    null_as_empty_collection: bool,
+    strict_canonical: bool,
+    max_depth: Option<usize>,
+    max_indefinite_containers: Option<usize>,
+    max_total_items: Option<usize>,
+    max_string_chunks: Option<usize>,
+    max_map_entries: Option<usize>,
+    progress: Option<(usize, Box<ProgressCallback>)>,
+    trace: Option<Box<TraceCallback>>,
+    errors: Option<Vec<Error>>,
+    tag_handlers: HashMap<u64, Rc<TagHandler>>,
+    depth: usize,
+    indefinite_containers: usize,
+    total_items: usize,
+    pending: Vec<u8>,
+    recording: usize,
+    recorded: Vec<u8>,
+    canonical: Option<bool>,
+    bignum_as_integer: bool,
+    capture: bool,
+    offset: usize,
+    input_len: Option<usize>,
 }
 
 impl<R: Read> Deserializer<R> {
     /// Creates the CBOR parser from an `std::io::Read`.
     #[inline]
     pub fn new(reader: R) -> Deserializer<R> {
-        Deserializer { reader: reader, first: None }
+        Deserializer {
+            reader: reader,
+            first: None,
+            padding: None,
+            lenient_numbers: false,
+            lenient_bools: false,
+            duplicate_tags: DuplicateTags::Preserve,
+            variant_tags: VariantTags::Reject,
+            unknown_simple_as_null: false,
+            null_as_empty_collection: true,
+            strict_canonical: false,
+            max_depth: None,
+            max_indefinite_containers: None,
+            max_total_items: None,
+            max_string_chunks: None,
+            max_map_entries: None,
+            progress: None,
+            trace: None,
+            errors: None,
+            tag_handlers: HashMap::new(),
+            depth: 0,
+            indefinite_containers: 0,
+            total_items: 0,
+            pending: Vec::new(),
+            recording: 0,
+            recorded: Vec::new(),
+            canonical: None,
+            bignum_as_integer: false,
+            capture: false,
+            offset: 0,
+            input_len: None,
+        }
+    }
+
+    /// Accepts any number of `padding` bytes after the value in `Deserializer::end` and between
+    /// the values of a `StreamDeserializer`.
+    ///
+    /// Block-aligned transports often pad messages with zero bytes. Note that `0x00` is also the
+    /// encoding of the integer zero, so trailing zeros are always taken as padding.
+    #[inline]
+    pub fn allow_padding(mut self, padding: Option<u8>) -> Deserializer<R> {
+        self.padding = padding;
+        self
+    }
+
+    /// Accepts text strings in place of numbers if they parse as the requested type.
+    ///
+    /// This only applies where a number is expected, for example for integer fields of a struct.
+    /// By default such strings are rejected.
+    #[inline]
+    pub fn lenient_numbers(mut self, lenient: bool) -> Deserializer<R> {
+        self.lenient_numbers = lenient;
+        self
+    }
+
+    /// Accepts the integers 0 and 1 in place of the booleans false and true.
+    ///
+    /// Other integers are still rejected where a boolean is expected.
+    #[inline]
+    pub fn lenient_bools(mut self, lenient: bool) -> Deserializer<R> {
+        self.lenient_bools = lenient;
+        self
+    }
+
+    /// Sets what happens to tags that directly repeat the tag before them.
+    #[inline]
+    pub fn duplicate_tags(mut self, duplicate_tags: DuplicateTags) -> Deserializer<R> {
+        self.duplicate_tags = duplicate_tags;
+        self
+    }
+
+    /// Sets whether a tag number may select the variant of an enum.
+    #[inline]
+    pub fn variant_tags(mut self, variant_tags: VariantTags) -> Deserializer<R> {
+        self.variant_tags = variant_tags;
+        self
+    }
+
+    /// Decodes simple values without an assigned meaning like null instead of failing.
+    #[inline]
+    pub fn unknown_simple_as_null(mut self, enabled: bool) -> Deserializer<R> {
+        self.unknown_simple_as_null = enabled;
+        self
+    }
+
+    /// Sets whether null and undefined decode as empty collections where a sequence, a map or a
+    /// byte string is expected.
+    ///
+    /// This is the default. Disabling it rejects null and undefined there, an `Option` still
+    /// decodes null as `None`.
+    #[inline]
+    pub fn null_as_empty_collection(mut self, enabled: bool) -> Deserializer<R> {
+        self.null_as_empty_collection = enabled;
+        self
+    }
+
+    /// Rejects input that is not encoded canonically with `Error::NonCanonical`.
+    ///
+    /// The rules are those of `from_slice_checked`: integers and lengths in their shortest form,
+    /// no indefinite lengths and map keys sorted without duplicates. A NaN has to be written as
+    /// the half precision float `0xf9 0x7e 0x00`, other NaNs are `Error::NonCanonicalNaN`.
+    /// Bignums have to be too large for a regular integer.
+    #[inline]
+    pub fn strict_canonical(mut self, enabled: bool) -> Deserializer<R> {
+        self.strict_canonical = enabled;
+        self.canonical = if enabled { Some(true) } else { None };
+        self
+    }
+
+    /// Limits how deep arrays, maps and tags may be nested.
+    ///
+    /// Like the other limits this also applies to values that are skipped, for example unknown
+    /// fields of a struct.
+    #[inline]
+    pub fn max_depth(mut self, depth: Option<usize>) -> Deserializer<R> {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Limits the number of arrays and maps of indefinite length in the input.
+    #[inline]
+    pub fn max_indefinite_containers(mut self, containers: Option<usize>) -> Deserializer<R> {
+        self.max_indefinite_containers = containers;
+        self
+    }
+
+    /// Limits the total number of array elements and map entries in the input.
+    #[inline]
+    pub fn max_total_items(mut self, items: Option<usize>) -> Deserializer<R> {
+        self.max_total_items = items;
+        self
+    }
+
+    /// Limits the number of chunks a byte or text string of indefinite length may consist of.
+    #[inline]
+    pub fn max_string_chunks(mut self, chunks: Option<usize>) -> Deserializer<R> {
+        self.max_string_chunks = chunks;
+        self
+    }
+
+    /// Limits the number of entries a single map may have.
+    ///
+    /// Maps of definite length are checked against their header before any entry is read.
+    #[inline]
+    pub fn max_map_entries(mut self, entries: Option<usize>) -> Deserializer<R> {
+        self.max_map_entries = entries;
+        self
+    }
+
+    /// Calls `callback` after every `every` array elements and map entries.
+    ///
+    /// The callback gets the number of elements and entries decoded so far. Returning
+    /// `ControlFlow::Break` aborts decoding with `Error::Aborted`, for example once a time budget
+    /// is used up.
+    #[inline]
+    pub fn on_progress<F>(mut self, every: usize, callback: F) -> Deserializer<R>
+        where F: FnMut(usize) -> ControlFlow<()> + 'static
+    {
+        self.progress = Some((cmp::max(every, 1), Box::new(callback)));
+        self
+    }
+
+    /// Calls `callback` with the initial byte of every data item before it is decoded.
+    ///
+    /// This does not change how the input is decoded, it helps to find out where decoding goes
+    /// wrong. Items that are skipped are reported too.
+    #[inline]
+    pub fn trace<F: FnMut(&Header) + 'static>(mut self, callback: F) -> Deserializer<R> {
+        self.trace = Some(Box::new(callback));
+        self
+    }
+
+    /// Decodes the values of tag `tag` with `handler`.
+    ///
+    /// The handler gets the tag number and a deserializer over the value inside of the tag with
+    /// the same settings. It has to consume that value and returns what is decoded in the place of
+    /// the tag. Registered tags take precedence over the tags this crate knows, other tags are
+    /// treated as before, so a `Value` keeps them and other types ignore them. A tag in the
+    /// returned value is interpreted like one in the input but without the registered handlers.
+    #[inline]
+    pub fn register_tag_handler<F>(mut self, tag: u64, handler: F) -> Deserializer<R>
+        where F: Fn(u64, &mut Deserializer<&[u8]>) -> Result<Value> + 'static
+    {
+        self.tag_handlers.insert(tag, Rc::new(handler));
+        self
+    }
+
+    /// Skips map entries that fail to decode and keeps their errors instead of failing.
+    ///
+    /// This only applies to maps that are decoded entry by entry with `MapVisitor::visit`, like
+    /// `HashMap`, `BTreeMap` and `Value`. Structs and malformed input still fail on the first
+    /// error. The collected errors are returned by `Deserializer::take_errors`.
+    #[inline]
+    pub fn collect_errors(mut self, enabled: bool) -> Deserializer<R> {
+        self.errors = if enabled { Some(Vec::new()) } else { None };
+        self
+    }
+
+    /// Returns the errors of the map entries skipped so far, see `Deserializer::collect_errors`.
+    #[inline]
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        self.errors.as_mut().map_or_else(Vec::new, mem::take)
+    }
+
+    /// Returns the length of the next array or map without consuming it.
+    ///
+    /// The length of a map is its number of entries, `None` stands for a container of
+    /// indefinite length. Any other kind of value results in an error.
+    pub fn peek_container_len(&mut self) -> Result<Option<usize>> {
+        if self.first.is_none() {
+            self.first = Some(try!(self.read_u8()));
+        }
+        let first = self.first.unwrap();
+        if first >> 5 != 4 && first >> 5 != 5 {
+            return Err(de::Error::invalid_type(de::Type::Seq));
+        }
+        let len = match first & 0b000_11111 {
+            n @ 0...23 => return Ok(Some(n as usize)),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            31 => return Ok(None),
+            _ => return Err(Error::Syntax),
+        };
+        let n = try!(self.peek_bytes(len)).iter().fold(0, |n, &byte| n << 8 | byte as u64);
+        if n > MAX_SEQ_LEN {
+            return Err(Error::Syntax);
+        }
+        Ok(Some(n as usize))
     }
 
     /// The `Deserializer::end` method should be called after a value has been fully deserialized.
     /// This allows the `Deserializer` to validate that the input stream is at the end.
+    ///
+    /// If it is not the error tells the major type of the next byte and, for a deserializer
+    /// created with `Deserializer::from_slice`, how many bytes are left. The rest of the input is
+    /// not read.
     #[inline]
     pub fn end(&mut self) -> Result<()> {
-        if try!(self.read(&mut [0; 1])) == 0 {
-            Ok(())
-        } else {
-            Err(Error::TrailingBytes)
+        let mut byte = [0; 1];
+        while try!(self.read(&mut byte)) != 0 {
+            if Some(byte[0]) != self.padding {
+                let remaining = self.input_len.map(|len| len + 1 - self.offset);
+                return Err(Error::TrailingBytes {
+                    next_major: byte[0] >> 5,
+                    remaining: remaining,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn parse_number<T, V>(&mut self, mut visitor: V) -> Result<V::Value>
+        where T: FromStr + ValueDeserializer<Error>,
+              V: Visitor,
+    {
+        if self.first.is_none() {
+            self.first = Some(try!(self.read_u8()));
+        }
+        let first = self.first.unwrap();
+        // Small integers are by far the most common numbers, they skip the general dispatch.
+        if first <= 23 && self.trace.is_none() {
+            self.first = None;
+            return visitor.visit_u8(first);
+        }
+        if first >> 5 != 3 {
+            self.bignum_as_integer = true;
+            let result = de::Deserializer::deserialize(self, visitor);
+            self.bignum_as_integer = false;
+            return result;
+        }
+        if !self.lenient_numbers {
+            self.first = None;
+            return Err(de::Error::invalid_type(de::Type::Str));
+        }
+        let string = try!(String::deserialize(self));
+        match string.parse::<T>() {
+            Ok(n) => de::Deserializer::deserialize(&mut n.into_deserializer(), visitor),
+            Err(_) => Err(de::Error::invalid_value(&string)),
         }
     }
 
     #[inline]
     fn parse_value<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        let first = self.first.unwrap();
+        if let Some(ref mut callback) = self.trace {
+            callback(&Header {
+                offset: self.offset - 1,
+                depth: self.depth,
+                major: first >> 5,
+                info: first & 0b000_11111,
+            });
+        }
+        match first >> 5 {
+            4...6 => {}
+            _ => return self.parse_item(visitor),
+        }
+        let result = self.enter_nested(first).and_then(|()| self.parse_item(visitor));
+        self.depth -= 1;
+        result
+    }
+
+    /// Checks the limits before an array, map or tag is parsed.
+    fn enter_nested(&mut self, first: u8) -> Result<()> {
+        self.depth += 1;
+        if let Some(max) = self.max_depth {
+            if self.depth > max {
+                return Err(Error::DepthLimitExceeded);
+            }
+        }
+        if first & 0b000_11111 == 31 && first >> 5 != 6 {
+            self.indefinite_containers += 1;
+            if let Some(max) = self.max_indefinite_containers {
+                if self.indefinite_containers > max {
+                    return Err(Error::TooManyIndefiniteContainers);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_item<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
         let first = self.first.unwrap();
         self.first = None;
+        let capture = mem::replace(&mut self.capture, false);
+        if first >> 5 == 6 && !self.tag_handlers.is_empty() {
+            let handler = try!(self.peek_tag_number(first))
+                .and_then(|tag| self.tag_handlers.get(&tag).cloned());
+            if let Some(handler) = handler {
+                let tag = try!(self.parse_tag_number(first));
+                let (_, bytes) = try!(self.copy_value());
+                let value = try!(self.with_child(&bytes, |de| {
+                    let value = try!(handler(tag, de));
+                    try!(de.end());
+                    Ok(value)
+                }));
+                let bytes = try!(ser::to_vec(&value));
+                let mut de = self.child(&bytes);
+                de.capture = capture;
+                return de::Deserializer::deserialize(&mut de, visitor);
+            }
+        }
+        if first >> 5 == 6 && capture {
+            let tag = try!(self.parse_tag_number(first));
+            if tag == SELF_DESCRIBE_TAG {
+                self.capture = true;
+                self.first = Some(try!(self.read_u8()));
+                return self.parse_value(visitor);
+            }
+            return visitor.visit_newtype_struct(&mut TagVisitor {
+                number: tag,
+                tag: Some(tag),
+                value: false,
+                de: self,
+            });
+        }
         match (first & 0b111_00000) >> 5 {
             0 => self.parse_uint(first, visitor),
             1 => self.parse_int(first, visitor),
@@ -53,15 +481,20 @@ impl<R: Read> Deserializer<R> {
 
     #[inline]
     fn parse_additional_information(&mut self, first: u8) -> Result<Option<u64>> {
-        Ok(Some(match first & 0b000_11111 {
-            n @ 0...23 => n as u64,
+        let n = match first & 0b000_11111 {
+            n @ 0...23 => return Ok(Some(n as u64)),
             24 => try!(self.read_u8()) as u64,
             25 => try!(self.read_u16::<BigEndian>()) as u64,
             26 => try!(self.read_u32::<BigEndian>()) as u64,
             27 => try!(self.read_u64::<BigEndian>()) as u64,
-            31 => return Ok(None),
+            31 => {
+                try!(self.observe_canonical(false));
+                return Ok(None);
+            }
             _ => return Err(Error::Syntax),
-        }))
+        };
+        try!(self.observe_canonical(n >= shortest_argument(first)));
+        Ok(Some(n))
     }
 
     fn parse_size_information(&mut self, first: u8) -> Result<Option<usize>> {
@@ -75,6 +508,10 @@ impl<R: Read> Deserializer<R> {
 
     #[inline]
     fn parse_uint<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
+        if self.canonical.is_some() {
+            let n = try!(self.parse_additional_information(first));
+            return visitor.visit_u64(try!(n.ok_or(Error::Syntax)));
+        }
         match first & 0b000_11111 {
             n @ 0...23 => visitor.visit_u8(n),
             24 => visitor.visit_u8(try!(self.read_u8())),
@@ -87,6 +524,10 @@ impl<R: Read> Deserializer<R> {
 
     #[inline]
     fn parse_int<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
+        if self.canonical.is_some() {
+            let n = try!(self.parse_additional_information(first));
+            return visitor.visit_i64(-1 - try!(n.ok_or(Error::Syntax)) as i64);
+        }
         match first & 0b000_11111 {
             n @ 0...23 => visitor.visit_i8(-1 - n as i8),
             24 => visitor.visit_i16(-1 - try!(self.read_u8()) as i16),
@@ -101,13 +542,18 @@ impl<R: Read> Deserializer<R> {
     fn parse_byte_buf<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
         if let Some(n) = try!(self.parse_size_information(first)) {
             let mut buf = vec![0; n];
-            try!(self.reader.read_exact(&mut buf));
+            try!(self.read_exact(&mut buf));
             visitor.visit_byte_buf(buf)
         } else {
             let mut bytes = Vec::new();
+            let mut chunks = 0;
             loop {
                 match ByteBuf::deserialize(self) {
-                    Ok(value) => bytes.append(&mut value.into()),
+                    Ok(value) => {
+                        chunks += 1;
+                        try!(self.check_string_chunks(chunks));
+                        bytes.append(&mut value.into());
+                    }
                     Err(Error::StopCode) => break,
                     Err(e) => return Err(e),
                 }
@@ -120,13 +566,21 @@ impl<R: Read> Deserializer<R> {
     fn parse_string<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
         if let Some(n) = try!(self.parse_size_information(first)) {
             let mut buf = vec![0; n];
-            try!(self.reader.read_exact(&mut buf));
-            visitor.visit_string(try!(String::from_utf8(buf)))
+            try!(self.read_exact(&mut buf));
+            match String::from_utf8(buf) {
+                Ok(string) => visitor.visit_string(string),
+                Err(e) => Err(Error::InvalidUtf8(self.offset - n + e.utf8_error().valid_up_to())),
+            }
         } else {
             let mut string = String::new();
+            let mut chunks = 0;
             loop {
                 match String::deserialize(self) {
-                    Ok(value) => string.push_str(&value[..]),
+                    Ok(value) => {
+                        chunks += 1;
+                        try!(self.check_string_chunks(chunks));
+                        string.push_str(&value[..]);
+                    }
                     Err(Error::StopCode) => break,
                     Err(e) => return Err(e),
                 }
@@ -135,6 +589,14 @@ impl<R: Read> Deserializer<R> {
         }
     }
 
+    #[inline]
+    fn check_string_chunks(&self, chunks: usize) -> Result<()> {
+        match self.max_string_chunks {
+            Some(max) if chunks > max => Err(Error::TooManyChunks),
+            _ => Ok(()),
+        }
+    }
+
     #[inline]
     fn parse_seq<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
         let n = try!(self.parse_size_information(first));
@@ -144,199 +606,1325 @@ impl<R: Read> Deserializer<R> {
     #[inline]
     fn parse_map<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
         let n = try!(self.parse_size_information(first));
+        match (n, self.max_map_entries) {
+            (Some(n), Some(max)) if n > max => return Err(Error::TooManyMapEntries),
+            _ => {}
+        }
         visitor.visit_map(CompositeVisitor::new(self, n.map(|x| x as usize)))
     }
 
     #[inline]
     fn parse_tag<V: Visitor>(&mut self, first: u8, visitor: V) -> Result<V::Value> {
-        try!(self.parse_additional_information(first));
+        match try!(self.parse_tag_number(first)) {
+            tag @ POSITIVE_BIGNUM_TAG | tag @ NEGATIVE_BIGNUM_TAG => {
+                return self.parse_bignum(tag, visitor)
+            }
+            RATIONAL_TAG => return self.parse_rational(visitor),
+            tag @ 64...87 => return self.parse_typed_array(tag, visitor),
+            DAYS_TAG => return self.parse_days(visitor),
+            _ => {}
+        }
         self.first = Some(try!(self.read_u8()));
         self.parse_value(visitor)
     }
 
-    #[inline]
-    fn parse_simple_value<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
-        #[inline]
-        fn decode_f16(half: u16) -> f32 {
-            let exp: u16 = half >> 10 & 0x1f;
-            let mant: u16 = half & 0x3ff;
-            let val: f32 = if exp == 0 {
-                (mant as f32) * (2.0f32).powi(-24)
-            } else if exp != 31 {
-                (mant as f32 + 1024f32) * (2.0f32).powi(exp as i32 - 25)
-            } else if mant == 0 {
-                ::std::f32::INFINITY
-            } else {
-                ::std::f32::NAN
+    /// Parses the number of a tag and deals with identical tags following it.
+    ///
+    /// Tags have no indefinite length, the additional information 31 is a syntax error.
+    fn parse_tag_number(&mut self, first: u8) -> Result<u64> {
+        let tag = match try!(self.parse_additional_information(first)) {
+            Some(tag) => tag,
+            None => return Err(Error::Syntax),
+        };
+        if self.duplicate_tags == DuplicateTags::Preserve {
+            return Ok(tag);
+        }
+        loop {
+            let next = try!(self.peek_bytes(1))[0];
+            let len = match next {
+                0xc0...0xd7 => 1,
+                0xd8...0xdb => 1 + (1 << (next - 0xd8)),
+                _ => return Ok(tag),
             };
-            if half & 0x8000 != 0 {
-                -val
+            let header = try!(self.peek_bytes(len));
+            let next_tag = if len == 1 {
+                next as u64 & 0b000_11111
             } else {
-                val
+                header[1..].iter().fold(0, |n, &byte| n << 8 | byte as u64)
+            };
+            if next_tag != tag {
+                return Ok(tag);
             }
-        }
-        match first & 0b000_11111 {
-            20 => visitor.visit_bool(false),
-            21 => visitor.visit_bool(true),
-            22 => visitor.visit_unit(),
-            23 => visitor.visit_unit(),
-            25 => visitor.visit_f32(decode_f16(try!(self.read_u16::<BigEndian>()))),
-            26 => visitor.visit_f32(try!(self.read_f32::<BigEndian>())),
-            27 => visitor.visit_f64(try!(self.read_f64::<BigEndian>())),
-            31 => Err(Error::StopCode),
-            _ => Err(Error::Syntax),
+            if self.duplicate_tags == DuplicateTags::Reject {
+                return Err(Error::DuplicateTag(next_tag));
+            }
+            let mut header = [0; 9];
+            try!(self.read_exact(&mut header[..len]));
         }
     }
-}
 
-impl<R: Read> de::Deserializer for Deserializer<R> {
-    type Error = Error;
+    /// Returns the number of a tag without consuming it.
+    fn peek_tag_number(&mut self, first: u8) -> Result<Option<u64>> {
+        let len = match first & 0b000_11111 {
+            n @ 0...23 => return Ok(Some(n as u64)),
+            n @ 24...27 => 1 << (n - 24),
+            _ => return Ok(None),
+        };
+        let bytes = try!(self.peek_bytes(len));
+        Ok(Some(bytes.iter().fold(0, |n, &byte| n << 8 | byte as u64)))
+    }
 
-    #[inline]
-    fn deserialize<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
-        if self.first.is_none() {
-            self.first = Some(try!(self.read_u8()));
+    /// Returns the next `n` bytes without consuming them.
+    fn peek_bytes(&mut self, n: usize) -> Result<&[u8]> {
+        let start = self.pending.len();
+        if start < n {
+            self.pending.resize(n, 0);
+            if let Err(e) = self.reader.read_exact(&mut self.pending[start..]) {
+                self.pending.truncate(start);
+                return Err(From::from(e));
+            }
         }
-        let result = self.parse_value(visitor);
-        self.first = None;
-        result
+        Ok(&self.pending[..n])
     }
 
-    #[inline]
-    fn deserialize_option<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
-        self.first = Some(try!(self.read_u8()));
-        if self.first == Some(0b111_10110) {
-            self.first = None;
-            visitor.visit_none()
-        } else {
-            visitor.visit_some(self)
+    /// Parses the byte string of a bignum (tags 2 and 3).
+    ///
+    /// Where an integer is expected bignums that fit into a `u64` or an `i64` are visited as these
+    /// integers, otherwise bignums are visited as their byte string. If the tag holds something
+    /// else than a byte string it is ignored like other tags.
+    fn parse_bignum<V: Visitor>(&mut self, tag: u64, mut visitor: V) -> Result<V::Value> {
+        let integer = mem::replace(&mut self.bignum_as_integer, false);
+        let first = try!(self.read_u8());
+        self.first = Some(first);
+        if first >> 5 != 2 {
+            return self.parse_value(visitor);
+        }
+        let bytes: Vec<u8> = try!(ByteBuf::deserialize(self)).into();
+        let n = match try!(self.small_bignum(&bytes)) {
+            Some(n) if integer => n,
+            _ => return visitor.visit_byte_buf(bytes),
+        };
+        match tag {
+            POSITIVE_BIGNUM_TAG => visitor.visit_u64(n),
+            _ if n <= ::std::i64::MAX as u64 => visitor.visit_i64(-1 - n as i64),
+            _ => visitor.visit_byte_buf(bytes),
         }
     }
-    #[inline]
-    fn deserialize_newtype_struct<V>(
-        &mut self,
-        _name: &'static str,
-        mut visitor: V
-    ) -> Result<V::Value>
-        where V: de::Visitor,
-    {
-        visitor.visit_newtype_struct(self)
+
+    /// Returns the value of a bignum that fits into a `u64`.
+    ///
+    /// Such small bignums are not canonical as they could be regular integers.
+    fn small_bignum(&mut self, bytes: &[u8]) -> Result<Option<u64>> {
+        let start = bytes.iter().position(|&byte| byte != 0).unwrap_or(bytes.len());
+        if bytes.len() - start > 8 {
+            return Ok(None);
+        }
+        try!(self.observe_canonical(false));
+        if self.strict_canonical {
+            return Err(Error::NonCanonical);
+        }
+        Ok(Some(bytes[start..].iter().fold(0, |n, &byte| n << 8 | byte as u64)))
     }
 
-    #[inline]
-    fn deserialize_enum<V: EnumVisitor>(&mut self,
-            _enum: &'static str, 
-            _variants: &'static [&'static str],
-            mut visitor: V) -> Result<V::Value> {
+    /// Parses the `[numerator, denominator]` array of a rational number (tag 30).
+    ///
+    /// The numerator has to fit into an `i64` and the denominator has to be a non-zero unsigned
+    /// integer, the array is visited as a sequence of these two integers. An indefinite length
+    /// array and a numerator that needs a bignum are syntax errors.
+    fn parse_rational<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
+        if try!(self.read_u8()) != 0x82 {
+            return Err(Error::Syntax);
+        }
         let first = try!(self.read_u8());
-        let items = match (first & 0b111_00000) >> 5 {
-            3 => {
-                self.first = Some(first);
-                Some(0)
-            }
-            4 => try!(self.parse_size_information(first)),
+        let numerator = match (first >> 5, try!(self.parse_additional_information(first))) {
+            (0, Some(n)) if n <= ::std::i64::MAX as u64 => n as i64,
+            (1, Some(n)) if n <= ::std::i64::MAX as u64 => -1 - n as i64,
             _ => return Err(Error::Syntax),
         };
-        visitor.visit(CompositeVisitor::new(self, items))
+        let first = try!(self.read_u8());
+        let denominator = match (first >> 5, try!(self.parse_additional_information(first))) {
+            (0, Some(n)) if n != 0 => n,
+            _ => return Err(Error::Syntax),
+        };
+        visitor.visit_seq(RationalVisitor {
+            numerator: Some(numerator),
+            denominator: Some(denominator),
+        })
     }
-}
 
-impl<R: Read> Read for Deserializer<R> {
-    #[inline]
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.reader.read(buf)
+    /// Checks the array of a rational number like `parse_rational` without consuming it.
+    fn check_rational(&mut self) -> Result<()> {
+        if try!(self.peek_bytes(1))[0] != 0x82 {
+            return Err(Error::Syntax);
+        }
+        let mut offset = 1;
+        let mut integers = [(0, 0); 2];
+        for integer in &mut integers {
+            let first = try!(self.peek_bytes(offset + 1))[offset];
+            let len = match first & 0b000_11111 {
+                0...23 => 0,
+                n @ 24...27 => 1 << (n - 24),
+                _ => return Err(Error::Syntax),
+            };
+            let bytes = try!(self.peek_bytes(offset + 1 + len));
+            let n = if len == 0 {
+                first as u64 & 0b000_11111
+            } else {
+                bytes[offset + 1..].iter().fold(0, |n, &byte| n << 8 | byte as u64)
+            };
+            *integer = (first >> 5, n);
+            offset += 1 + len;
+        }
+        match integers {
+            [(0, n), (0, d)] | [(1, n), (0, d)] if n <= ::std::i64::MAX as u64 && d != 0 => Ok(()),
+            _ => Err(Error::Syntax),
+        }
     }
-}
 
-struct CompositeVisitor<'a, R: 'a + Read> {
-    de: &'a mut Deserializer<R>,
-    items: Option<usize>,
-}
-
-impl<'a, R: 'a + Read> CompositeVisitor<'a, R> {
-    #[inline]
-    fn new(de: &'a mut Deserializer<R>, items: Option<usize>) -> Self {
-        CompositeVisitor {
-            de: de,
-            items: items,
+    /// Parses a date given as the number of days since 1970-01-01 (tag 100, RFC 8943).
+    ///
+    /// The days are visited as an integer, negative for dates before the epoch.
+    fn parse_days<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        let first = try!(self.read_u8());
+        if first >> 5 > 1 {
+            return Err(Error::Syntax);
         }
+        self.first = Some(first);
+        self.parse_value(visitor)
     }
-    
-    fn _visit<T: Deserialize>(&mut self) -> Result<Option<T>> {
-        match self.items {
-            Some(0) => return Ok(None),
-            Some(ref mut n) => *n -= 1,
-            _ => {}
+
+    /// Parses the byte string of a typed array (tags 64 to 87, RFC 8746).
+    ///
+    /// The elements are visited as a sequence of numbers, 128 bit floats are not supported.
+    fn parse_typed_array<V: Visitor>(&mut self, tag: u64, mut visitor: V) -> Result<V::Value> {
+        let size = match tag {
+            76 | 83 | 87 => return Err(Error::Syntax),
+            80...86 => 2 << (tag & 0b11),
+            _ => 1 << (tag & 0b11),
         };
-        match Deserialize::deserialize(self.de) {
-            Ok(value) => Ok(Some(value)),
-            Err(Error::StopCode) if self.items.is_none() => {
-                self.items = Some(0);
-                Ok(None)
-            }
-            Err(e) => Err(e),
+        let first = try!(self.read_u8());
+        if first >> 5 != 2 {
+            return Err(Error::Syntax);
         }
-    }
-
-    fn _end(&mut self) -> Result<()> {
-        if let Some(0) = self.items {
-            Ok(())
-        } else {
-            Err(Error::TrailingBytes)
+        self.first = Some(first);
+        let bytes: Vec<u8> = try!(ByteBuf::deserialize(self)).into();
+        if !bytes.len().is_multiple_of(size) {
+            return Err(Error::Syntax);
         }
+        visitor.visit_seq(TypedArrayVisitor {
+            tag: tag,
+            size: size,
+            bytes: bytes,
+            pos: 0,
+        })
     }
 
-    fn _size_hint(&self) -> (usize, Option<usize>) {
-        self.items.map_or((0, None), |n| (n, Some(n)))
+    /// Copies the encoding of the next value and visits it as a byte buffer.
+    fn parse_raw_value<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
+        let start = self.start_recording();
+        let result = self.skip_value();
+        let raw = self.stop_recording(start);
+        try!(result);
+        visitor.visit_byte_buf(raw)
     }
-}
-
-impl<'a, R: Read> de::SeqVisitor for CompositeVisitor<'a, R> {
-    type Error = Error;
-    fn visit<T: Deserialize>(&mut self) -> Result<Option<T>> { self._visit() }
-    fn end(&mut self) -> Result<()> { self._end() }
-    fn size_hint(&self) -> (usize, Option<usize>) { self._size_hint() }
-}
 
-impl<'a, R: Read> de::MapVisitor for CompositeVisitor<'a, R> {
-    type Error = Error;
-    fn visit_key<K: Deserialize>(&mut self) -> Result<Option<K>> { self._visit() }
-    fn visit_value<V: Deserialize>(&mut self) -> Result<V> {
-        Deserialize::deserialize(self.de)
+    /// Parses how the next value is encoded.
+    ///
+    /// The limits on nesting and on the number of items apply like when decoding.
+    fn parse_annotation(&mut self) -> Result<Annotation> {
+        let first = match self.first.take() {
+            Some(first) => first,
+            None => try!(self.read_u8()),
+        };
+        if first == 0xff {
+            return Err(Error::StopCode);
+        }
+        match first >> 5 {
+            4...6 => {}
+            _ => return self.parse_annotation_item(first),
+        }
+        let result = self.enter_nested(first).and_then(|()| self.parse_annotation_item(first));
+        self.depth -= 1;
+        result
     }
-    fn end(&mut self) -> Result<()> { self._end() }
-    fn size_hint(&self) -> (usize, Option<usize>) { self._size_hint() }
-}
 
-impl<'a, R: Read> de::VariantVisitor for CompositeVisitor<'a, R> {
+    fn parse_annotation_item(&mut self, first: u8) -> Result<Annotation> {
+        let mut annotation = Annotation {
+            width: match first & 0b000_11111 {
+                24 => 1,
+                25 => 2,
+                26 => 4,
+                27 => 8,
+                _ => 0,
+            },
+            indefinite: first & 0b000_11111 == 31,
+            children: Vec::new(),
+        };
+        let children = match first >> 5 {
+            0 | 1 | 7 => {
+                try!(self.parse_additional_information(first));
+                Some(0)
+            }
+            2 | 3 => match try!(self.parse_size_information(first)) {
+                Some(n) => {
+                    try!(self.read_exact(&mut vec![0; n]));
+                    Some(0)
+                }
+                None => None,
+            },
+            4 => try!(self.parse_size_information(first)),
+            5 => {
+                let n = try!(self.parse_size_information(first));
+                match (n, self.max_map_entries) {
+                    (Some(n), Some(max)) if n > max => return Err(Error::TooManyMapEntries),
+                    _ => {}
+                }
+                n.map(|n| 2 * n)
+            }
+            _ => {
+                try!(self.parse_additional_information(first));
+                Some(1)
+            }
+        };
+        let major = first >> 5;
+        loop {
+            if children == Some(annotation.children.len()) {
+                break;
+            }
+            let child = match self.parse_annotation() {
+                Ok(child) => child,
+                Err(Error::StopCode) if children.is_none() => break,
+                Err(e) => return Err(e),
+            };
+            annotation.children.push(child);
+            let n = annotation.children.len();
+            match major {
+                2 | 3 => try!(self.check_string_chunks(n)),
+                4 => try!(self.count_item()),
+                5 if n % 2 == 1 => {
+                    try!(self.count_item());
+                    match self.max_map_entries {
+                        Some(max) if n / 2 + 1 > max => return Err(Error::TooManyMapEntries),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(annotation)
+    }
+
+    /// Counts an array element or map entry against `max_total_items` and reports progress.
+    fn count_item(&mut self) -> Result<()> {
+        self.total_items += 1;
+        match self.max_total_items {
+            Some(max) if self.total_items > max => return Err(Error::TooManyItems),
+            _ => {}
+        }
+        if let Some((every, ref mut callback)) = self.progress {
+            if self.total_items.is_multiple_of(every) && callback(self.total_items).is_break() {
+                return Err(Error::Aborted);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the encoding of the next value together with its annotation.
+    ///
+    /// The value is checked against the limits but not counted, it is decoded from the copy later.
+    fn copy_value(&mut self) -> Result<(Annotation, Vec<u8>)> {
+        let indefinite_containers = self.indefinite_containers;
+        let total_items = self.total_items;
+        let progress = self.progress.take();
+        let start = self.start_recording();
+        let result = self.parse_annotation();
+        let bytes = self.stop_recording(start);
+        self.indefinite_containers = indefinite_containers;
+        self.total_items = total_items;
+        self.progress = progress;
+        result.map(|annotation| (annotation, bytes))
+    }
+
+    /// Reads past the next value without keeping it.
+    #[inline]
+    fn skip_value(&mut self) -> Result<()> {
+        IgnoredAny::deserialize(self).map(|_| ())
+    }
+
+    /// Starts copying the bytes of the next value, returns where they start in `self.recorded`.
+    fn start_recording(&mut self) -> usize {
+        self.recording += 1;
+        if self.recording == 1 {
+            self.recorded.extend(self.first);
+            0
+        } else {
+            // The first byte has already been recorded when it was read.
+            self.recorded.len() - self.first.map_or(0, |_| 1)
+        }
+    }
+
+    fn stop_recording(&mut self, start: usize) -> Vec<u8> {
+        self.recording -= 1;
+        let bytes = self.recorded[start..].to_vec();
+        if self.recording == 0 {
+            self.recorded.clear();
+        }
+        bytes
+    }
+
+    /// Creates a deserializer for bytes that are not part of the input with the same settings.
+    ///
+    /// Callbacks and tag handlers are not passed on.
+    fn child<'b>(&self, bytes: &'b [u8]) -> Deserializer<&'b [u8]> {
+        let mut de = Deserializer::new(bytes);
+        de.lenient_numbers = self.lenient_numbers;
+        de.lenient_bools = self.lenient_bools;
+        de.duplicate_tags = self.duplicate_tags;
+        de.variant_tags = self.variant_tags;
+        de.unknown_simple_as_null = self.unknown_simple_as_null;
+        de.null_as_empty_collection = self.null_as_empty_collection;
+        de.strict_canonical = self.strict_canonical;
+        de.max_depth = self.max_depth;
+        de.max_indefinite_containers = self.max_indefinite_containers;
+        de.max_total_items = self.max_total_items;
+        de.max_string_chunks = self.max_string_chunks;
+        de.max_map_entries = self.max_map_entries;
+        de.errors = self.errors.as_ref().map(|_| Vec::new());
+        de.depth = self.depth;
+        de.indefinite_containers = self.indefinite_containers;
+        de.total_items = self.total_items;
+        de
+    }
+
+    /// Decodes bytes copied from the input just before with `f`.
+    ///
+    /// The deserializer passed to `f` continues where this one is, with the same settings,
+    /// callbacks and tag handlers. The items it decodes count towards the limits of this one.
+    fn with_child<T, F>(&mut self, bytes: &[u8], f: F) -> Result<T>
+        where F: FnOnce(&mut Deserializer<&[u8]>) -> Result<T>
+    {
+        let mut de = self.child(bytes);
+        de.canonical = self.canonical;
+        de.offset = self.offset - bytes.len();
+        de.progress = self.progress.take();
+        de.trace = self.trace.take();
+        de.tag_handlers = self.tag_handlers.clone();
+        let result = f(&mut de);
+        self.progress = de.progress.take();
+        self.trace = de.trace.take();
+        self.indefinite_containers = de.indefinite_containers;
+        self.total_items = de.total_items;
+        if de.canonical == Some(false) {
+            self.canonical = Some(false);
+        }
+        if let Some(ref mut errors) = self.errors {
+            errors.append(&mut de.take_errors());
+        }
+        result
+    }
+
+    /// Decodes the next value as a `Value` together with an annotation of how it was encoded.
+    ///
+    /// See `from_slice_annotated`, unlike it this applies the limits set on the deserializer.
+    pub fn deserialize_annotated(&mut self) -> Result<(Value, Annotation)> {
+        let (annotation, bytes) = try!(self.copy_value());
+        let value = try!(self.with_child(&bytes, |de| Value::deserialize(de)));
+        Ok((value, annotation))
+    }
+
+    /// Takes note of non-canonical input if `from_slice_checked` is looking for it and rejects it
+    /// in strict canonical mode.
+    #[inline]
+    fn observe_canonical(&mut self, canonical: bool) -> Result<()> {
+        if !canonical && self.canonical.is_some() {
+            if self.strict_canonical {
+                return Err(Error::NonCanonical);
+            }
+            self.canonical = Some(false);
+        }
+        Ok(())
+    }
+
+    /// Rejects null and undefined in place of a collection unless `null_as_empty_collection` is
+    /// set, serde turns them into empty collections.
+    #[inline]
+    fn check_null_collection(&mut self, expected: de::Type) -> Result<()> {
+        if self.first.is_none() {
+            self.first = Some(try!(self.read_u8()));
+        }
+        match self.first {
+            Some(0b111_10110) | Some(0b111_10111) if !self.null_as_empty_collection => {
+                Err(de::Error::invalid_type(expected))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Deals with a NaN that is not encoded canonically.
+    #[inline]
+    fn check_nan(&mut self, non_canonical: bool) -> Result<()> {
+        if non_canonical {
+            if self.strict_canonical {
+                return Err(Error::NonCanonicalNaN);
+            }
+            try!(self.observe_canonical(false));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn parse_simple_value<V: Visitor>(&mut self, first: u8, mut visitor: V) -> Result<V::Value> {
+        match first & 0b000_11111 {
+            20 => visitor.visit_bool(false),
+            21 => visitor.visit_bool(true),
+            22 => visitor.visit_unit(),
+            23 => visitor.visit_unit(),
+            25 => {
+                let half = try!(self.read_u16::<BigEndian>());
+                let value = decode_f16(half);
+                try!(self.check_nan(value.is_nan() && half != 0x7e00));
+                visitor.visit_f32(value)
+            }
+            26 => {
+                let value = try!(self.read_f32::<BigEndian>());
+                try!(self.check_nan(value.is_nan()));
+                visitor.visit_f32(value)
+            }
+            27 => {
+                let value = try!(self.read_f64::<BigEndian>());
+                try!(self.check_nan(value.is_nan()));
+                visitor.visit_f64(value)
+            }
+            31 => Err(Error::StopCode),
+            24 if self.unknown_simple_as_null => {
+                try!(self.read_u8());
+                visitor.visit_unit()
+            }
+            0...19 if self.unknown_simple_as_null => visitor.visit_unit(),
+            _ => Err(Error::Syntax),
+        }
+    }
+}
+
+/// Returns the smallest argument that needs the length of argument announced by `first`.
+#[inline]
+fn shortest_argument(first: u8) -> u64 {
+    match first & 0b000_11111 {
+        24 => 24,
+        25 => 1 << 8,
+        26 => 1 << 16,
+        27 => 1 << 32,
+        _ => 0,
+    }
+}
+
+#[inline]
+fn decode_f16(half: u16) -> f32 {
+    let exp: u16 = half >> 10 & 0x1f;
+    let mant: u16 = half & 0x3ff;
+    let val: f32 = if exp == 0 {
+        (mant as f32) * (2.0f32).powi(-24)
+    } else if exp != 31 {
+        (mant as f32 + 1024f32) * (2.0f32).powi(exp as i32 - 25)
+    } else if mant == 0 {
+        ::std::f32::INFINITY
+    } else {
+        ::std::f32::NAN
+    };
+    if half & 0x8000 != 0 {
+        -val
+    } else {
+        val
+    }
+}
+
+impl<R: Read> de::Deserializer for Deserializer<R> {
     type Error = Error;
-    fn visit_variant<V: Deserialize>(&mut self) -> Result<V> {
-        Deserialize::deserialize(self.de)
+
+    #[inline]
+    fn deserialize<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        if self.first.is_none() {
+            self.first = Some(try!(self.read_u8()));
+        }
+        let result = self.parse_value(visitor);
+        self.first = None;
+        result
+    }
+
+    #[inline]
+    fn deserialize_bool<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
+        if self.first.is_none() {
+            self.first = Some(try!(self.read_u8()));
+        }
+        let first = self.first.unwrap();
+        if !self.lenient_bools || first >> 5 != 0 {
+            return self.deserialize(visitor);
+        }
+        self.first = None;
+        match try!(self.parse_additional_information(first)) {
+            Some(0) => visitor.visit_bool(false),
+            Some(1) => visitor.visit_bool(true),
+            _ => Err(de::Error::invalid_type(de::Type::U64)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_char<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
+        if self.first.is_none() {
+            self.first = Some(try!(self.read_u8()));
+        }
+        let first = self.first.unwrap();
+        if first >> 5 != 0 {
+            return self.deserialize(visitor);
+        }
+        // A code point written by `Serializer::chars_as_integers`.
+        self.first = None;
+        let n = try!(self.parse_additional_information(first));
+        match n.and_then(|n| if n <= 0x10ffff { ::std::char::from_u32(n as u32) } else { None }) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(de::Error::invalid_value("not a Unicode scalar value")),
+        }
+    }
+
+    #[inline]
+    fn deserialize_seq<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
+        try!(self.check_null_collection(de::Type::Seq));
+        // Byte strings are sequences of bytes, so that `Vec<u8>` and `Cow<[u8]>` can hold them.
+        // Any sequence of `u8` accepts them this way, like a `(u8, u8)` or a `VecDeque<u8>`, other
+        // element types are an error.
+        if self.first.map(|first| first >> 5) == Some(2) {
+            let bytes: Vec<u8> = try!(ByteBuf::deserialize(self)).into();
+            let len = bytes.len();
+            let bytes = bytes.into_iter().map(ByteDeserializer);
+            return visitor.visit_seq(SeqDeserializer::new(bytes, len));
+        }
+        self.deserialize(visitor)
+    }
+
+    #[inline]
+    fn deserialize_bytes<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        try!(self.check_null_collection(de::Type::Bytes));
+        self.deserialize(visitor)
+    }
+
+    #[inline]
+    fn deserialize_map<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        try!(self.check_null_collection(de::Type::Map));
+        self.deserialize(visitor)
+    }
+
+    #[inline]
+    fn deserialize_u8<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<u64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_u16<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<u64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_u32<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<u64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_u64<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<u64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_usize<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<u64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_i8<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<i64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_i16<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<i64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_i32<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<i64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_i64<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<i64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_isize<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<i64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_f32<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<f64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_f64<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.parse_number::<f64, _>(visitor)
+    }
+
+    #[inline]
+    fn deserialize_option<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
+        if self.first.is_none() {
+            self.first = Some(try!(self.read_u8()));
+        }
+        let unknown_simple = match self.first.unwrap() {
+            0xe0...0xf3 | 0xf8 => self.unknown_simple_as_null,
+            _ => false,
+        };
+        if unknown_simple {
+            try!(self.skip_value());
+            visitor.visit_none()
+        } else if self.first == Some(0b111_10110) {
+            self.first = None;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+    #[inline]
+    fn deserialize_newtype_struct<V>(
+        &mut self,
+        name: &'static str,
+        mut visitor: V
+    ) -> Result<V::Value>
+        where V: de::Visitor,
+    {
+        if name == RAW_VALUE_NAME {
+            return self.parse_raw_value(visitor);
+        }
+        if name == TAG_NAME {
+            self.capture = true;
+            let result = self.deserialize(visitor);
+            self.capture = false;
+            return result;
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    #[inline]
+    fn deserialize_enum<V: EnumVisitor>(&mut self,
+            _enum: &'static str,
+            variants: &'static [&'static str],
+            mut visitor: V) -> Result<V::Value> {
+        let first = match self.first.take() {
+            Some(first) => first,
+            None => try!(self.read_u8()),
+        };
+        let mut tag = None;
+        let (items, map) = match (first & 0b111_00000) >> 5 {
+            6 if self.variant_tags != VariantTags::Reject => {
+                tag = Some(try!(self.parse_tag_number(first)));
+                if self.variant_tags == VariantTags::Inline {
+                    (Some(1), true)
+                } else {
+                    let first = try!(self.read_u8());
+                    if first >> 5 != 4 {
+                        return Err(Error::Syntax);
+                    }
+                    (try!(self.parse_size_information(first)), false)
+                }
+            }
+            0 | 3 => {
+                self.first = Some(first);
+                (Some(0), false)
+            }
+            4 => match try!(self.parse_size_information(first)) {
+                Some(0) => return Err(Error::Syntax),
+                n => (n.map(|n| n - 1), false),
+            },
+            5 => match try!(self.parse_size_information(first)) {
+                n @ Some(1) | n @ None => (n, true),
+                _ => return Err(Error::Syntax),
+            },
+            _ => return Err(Error::Syntax),
+        };
+        visitor.visit(VariantVisitor::new(self, variants, tag, items, map))
+    }
+}
+
+impl<'a> Deserializer<&'a [u8]> {
+    /// Creates the CBOR parser from a `&[u8]` slice.
+    ///
+    /// Unlike with `Deserializer::new` the length of the input is known, so `Error::TrailingBytes`
+    /// can tell how many bytes are left.
+    #[inline]
+    pub fn from_slice(v: &'a [u8]) -> Deserializer<&'a [u8]> {
+        let mut de = Deserializer::new(v);
+        de.input_len = Some(v.len());
+        de
+    }
+}
+
+impl<R: Read> Read for Deserializer<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = if self.pending.is_empty() {
+            try!(self.reader.read(buf))
+        } else {
+            let n = cmp::min(buf.len(), self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            n
+        };
+        if self.recording > 0 {
+            self.recorded.extend_from_slice(&buf[..n]);
+        }
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// Tells whether an error concerns the input as a whole like exceeded limits and aborts, not just
+/// a single element or entry.
+fn concerns_input(error: &Error) -> bool {
+    match *error {
+        Error::DepthLimitExceeded |
+        Error::TooManyIndefiniteContainers |
+        Error::TooManyItems |
+        Error::TooManyChunks |
+        Error::TooManyMapEntries |
+        Error::Aborted => true,
+        _ => false,
+    }
+}
+
+struct CompositeVisitor<'a, R: 'a + Read> {
+    de: &'a mut Deserializer<R>,
+    items: Option<usize>,
+    index: usize,
+    last_key: Option<Vec<u8>>,
+}
+
+impl<'a, R: 'a + Read> CompositeVisitor<'a, R> {
+    #[inline]
+    fn new(de: &'a mut Deserializer<R>, items: Option<usize>) -> Self {
+        CompositeVisitor {
+            de: de,
+            items: items,
+            index: 0,
+            last_key: None,
+        }
     }
     
-    fn visit_unit(&mut self) -> Result<()> {
-        if self.items == Some(0) {
+    fn _visit<T: Deserialize>(&mut self) -> Result<Option<T>> {
+        match self.items {
+            Some(0) => return Ok(None),
+            Some(ref mut n) => *n -= 1,
+            _ => {}
+        };
+        match Deserialize::deserialize(self.de) {
+            Ok(value) => {
+                try!(self.de.count_item());
+                Ok(Some(value))
+            }
+            Err(Error::StopCode) if self.items.is_none() => {
+                self.items = Some(0);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn _end(&mut self) -> Result<()> {
+        if let Some(0) = self.items {
             Ok(())
         } else {
-            Err(Error::Syntax)
+            Err(Error::TrailingItems)
         }
     }
-    
-    fn visit_newtype<T: Deserialize>(&mut self) -> Result<T> {
+
+    fn _size_hint(&self) -> (usize, Option<usize>) {
+        self.items.map_or((0, None), |n| (n, Some(n)))
+    }
+
+    /// Counts the entries of a map for `Deserializer::max_map_entries`.
+    fn count_entry<K>(&mut self, key: Option<K>) -> Result<Option<K>> {
+        if key.is_some() {
+            self.index += 1;
+            match self.de.max_map_entries {
+                Some(max) if self.index > max => return Err(Error::TooManyMapEntries),
+                _ => {}
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl<'a, R: Read> de::SeqVisitor for CompositeVisitor<'a, R> {
+    type Error = Error;
+    fn visit<T: Deserialize>(&mut self) -> Result<Option<T>> {
+        let index = self.index;
+        self.index += 1;
+        self._visit().map_err(|e| {
+            if concerns_input(&e) {
+                e
+            } else {
+                Error::AtIndex(index, Box::new(e))
+            }
+        })
+    }
+    fn end(&mut self) -> Result<()> { self._end() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self._size_hint() }
+}
+
+impl<'a, R: Read> de::MapVisitor for CompositeVisitor<'a, R> {
+    type Error = Error;
+    fn visit<K: Deserialize, V: Deserialize>(&mut self) -> Result<Option<(K, V)>> {
+        if self.de.errors.is_none() {
+            return match try!(self.visit_key()) {
+                Some(key) => self.visit_value().map(|value| Some((key, value))),
+                None => Ok(None),
+            };
+        }
+        loop {
+            match self.items {
+                Some(0) => return Ok(None),
+                Some(ref mut n) => *n -= 1,
+                _ => {}
+            }
+            // The entry is copied first so that the next one can be decoded if it fails.
+            let mut entry = match self.de.copy_value() {
+                Ok((_, key)) => key,
+                Err(Error::StopCode) if self.items.is_none() => {
+                    self.items = Some(0);
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            };
+            match self.de.copy_value() {
+                Ok((_, mut value)) => entry.append(&mut value),
+                Err(Error::StopCode) => return Err(Error::Syntax),
+                Err(e) => return Err(e),
+            }
+            try!(self.count_entry(Some(())));
+            try!(self.de.count_item());
+            let result = self.de.with_child(&entry, |de| {
+                let key = try!(K::deserialize(de));
+                let value = try!(V::deserialize(de));
+                Ok((key, value))
+            });
+            match result {
+                Ok(entry) => return Ok(Some(entry)),
+                Err(e) => {
+                    if concerns_input(&e) {
+                        return Err(e);
+                    }
+                    self.de.errors.as_mut().unwrap().push(e);
+                }
+            }
+        }
+    }
+    fn visit_key<K: Deserialize>(&mut self) -> Result<Option<K>> {
+        if self.de.canonical.is_none() {
+            let key = try!(self._visit());
+            return self.count_entry(key);
+        }
+        let start = self.de.start_recording();
+        let result = self._visit();
+        let key = self.de.stop_recording(start);
+        if let Ok(Some(_)) = result {
+            let sorted = match self.last_key {
+                Some(ref last) => *last < key,
+                None => true,
+            };
+            try!(self.de.observe_canonical(sorted));
+            self.last_key = Some(key);
+        }
+        result.and_then(|key| self.count_entry(key))
+    }
+    fn visit_value<V: Deserialize>(&mut self) -> Result<V> {
         Deserialize::deserialize(self.de)
     }
+    fn end(&mut self) -> Result<()> { self._end() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self._size_hint() }
+}
 
-    fn visit_tuple<V: Visitor>(&mut self, len: usize, mut visitor: V) -> Result<V::Value> {
-        if self.items.is_some() && self.items != Some(len + 1) {
+/// A byte of a byte string visited as a sequence, it can only be decoded as a `u8`.
+struct ByteDeserializer(u8);
+
+impl de::Deserializer for ByteDeserializer {
+    type Error = Error;
+
+    #[inline]
+    fn deserialize<V: Visitor>(&mut self, _visitor: V) -> Result<V::Value> {
+        Err(de::Error::invalid_value("the elements of a byte string are `u8`"))
+    }
+
+    #[inline]
+    fn deserialize_u8<V: Visitor>(&mut self, mut visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.0)
+    }
+}
+
+impl ValueDeserializer<Error> for ByteDeserializer {
+    type Deserializer = ByteDeserializer;
+
+    #[inline]
+    fn into_deserializer(self) -> ByteDeserializer {
+        self
+    }
+}
+
+struct RationalVisitor {
+    numerator: Option<i64>,
+    denominator: Option<u64>,
+}
+
+impl de::SeqVisitor for RationalVisitor {
+    type Error = Error;
+
+    fn visit<T: Deserialize>(&mut self) -> Result<Option<T>> {
+        if let Some(numerator) = self.numerator.take() {
+            return Deserialize::deserialize(&mut numerator.into_deserializer()).map(Some);
+        }
+        match self.denominator.take() {
+            Some(denominator) => Deserialize::deserialize(&mut denominator.into_deserializer()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.denominator.is_none() {
+            Ok(())
+        } else {
+            Err(Error::TrailingItems)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.numerator.iter().count() + self.denominator.iter().count();
+        (n, Some(n))
+    }
+}
+
+/// Hands a tag and the value after it to a `Value` as a pair, see `value::TAG_NAME`.
+struct TagVisitor<'a, R: 'a + Read> {
+    number: u64,
+    tag: Option<u64>,
+    value: bool,
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'a, R: Read> de::Deserializer for TagVisitor<'a, R> {
+    type Error = Error;
+
+    #[inline]
+    fn deserialize<V: Visitor>(&mut self, visitor: V) -> Result<V::Value> {
+        self.de.deserialize(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(&mut self, name: &'static str, mut visitor: V)
+        -> Result<V::Value>
+        where V: Visitor,
+    {
+        if name == TAG_NAME {
+            return visitor.visit_seq(TagVisitor {
+                number: self.number,
+                tag: self.tag.take(),
+                value: false,
+                de: &mut *self.de,
+            });
+        }
+        self.de.deserialize_newtype_struct(name, visitor)
+    }
+}
+
+impl<'a, R: Read> de::SeqVisitor for TagVisitor<'a, R> {
+    type Error = Error;
+
+    fn visit<T: Deserialize>(&mut self) -> Result<Option<T>> {
+        if let Some(tag) = self.tag.take() {
+            return Deserialize::deserialize(&mut tag.into_deserializer()).map(Some);
+        }
+        if self.value {
+            return Ok(None);
+        }
+        self.value = true;
+        if self.number == RATIONAL_TAG {
+            try!(self.de.check_rational());
+        }
+        if self.number == POSITIVE_BIGNUM_TAG || self.number == NEGATIVE_BIGNUM_TAG {
+            if self.de.first.is_none() {
+                self.de.first = Some(try!(self.de.read_u8()));
+            }
+            if self.de.first.unwrap() >> 5 == 2 {
+                let bytes = try!(ByteBuf::deserialize(self.de));
+                try!(self.de.small_bignum(&bytes));
+                return Deserialize::deserialize(&mut bytes.into_deserializer()).map(Some);
+            }
+        }
+        Deserialize::deserialize(self.de).map(Some)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.value {
+            Ok(())
+        } else {
+            Err(Error::TrailingItems)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.tag.iter().count() + if self.value { 0 } else { 1 };
+        (n, Some(n))
+    }
+}
+
+struct TypedArrayVisitor {
+    tag: u64,
+    size: usize,
+    bytes: Vec<u8>,
+    pos: usize,
+}
+
+impl de::SeqVisitor for TypedArrayVisitor {
+    type Error = Error;
+
+    fn visit<T: Deserialize>(&mut self) -> Result<Option<T>> {
+        if self.pos == self.bytes.len() {
+            return Ok(None);
+        }
+        let element = &self.bytes[self.pos..self.pos + self.size];
+        self.pos += self.size;
+        let n = if self.tag & 0b100 == 0 {
+            element.iter().fold(0, |n, &byte| n << 8 | byte as u64)
+        } else {
+            element.iter().rev().fold(0, |n, &byte| n << 8 | byte as u64)
+        };
+        let shift = 64 - 8 * self.size;
+        match (self.tag >> 3 & 0b11, self.size) {
+            (0, _) => Deserialize::deserialize(&mut n.into_deserializer()),
+            (1, _) => Deserialize::deserialize(&mut ((n << shift) as i64 >> shift).into_deserializer()),
+            (_, 2) => Deserialize::deserialize(&mut decode_f16(n as u16).into_deserializer()),
+            (_, 4) => Deserialize::deserialize(&mut f32::from_bits(n as u32).into_deserializer()),
+            _ => Deserialize::deserialize(&mut f64::from_bits(n).into_deserializer()),
+        }.map(Some)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.pos == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(Error::TrailingItems)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = (self.bytes.len() - self.pos) / self.size;
+        (n, Some(n))
+    }
+}
+
+/// Visits an enum encoded as its variant followed by the payload, either in an array or as the
+/// only entry of a map. In a map the payload is always a single value.
+struct VariantVisitor<'a, R: 'a + Read> {
+    de: &'a mut Deserializer<R>,
+    variants: &'static [&'static str],
+    tag: Option<u64>,
+    items: Option<usize>,
+    map: bool,
+}
+
+impl<'a, R: 'a + Read> VariantVisitor<'a, R> {
+    #[inline]
+    fn new(de: &'a mut Deserializer<R>,
+           variants: &'static [&'static str],
+           tag: Option<u64>,
+           items: Option<usize>,
+           map: bool)
+           -> Self {
+        VariantVisitor {
+            de: de,
+            variants: variants,
+            tag: tag,
+            items: items,
+            map: map,
+        }
+    }
+
+    fn expect_items(&mut self, len: usize) -> Result<()> {
+        match self.items {
+            Some(n) if n != len => Err(Error::Syntax),
+            _ => Ok(()),
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        if self.items.is_none() && try!(self.de.read_u8()) != 0xff {
             return Err(Error::Syntax);
         }
-        let seq = CompositeVisitor::new(self.de, Some(len));
-        visitor.visit_seq(seq)
+        Ok(())
     }
-    
+}
+
+impl<'a, R: Read> de::VariantVisitor for VariantVisitor<'a, R> {
+    type Error = Error;
+
+    fn visit_variant<V: Deserialize>(&mut self) -> Result<V> {
+        if let Some(index) = self.tag {
+            return match self.variants.get(index as usize) {
+                Some(variant) => V::deserialize(&mut variant.into_deserializer()),
+                None => Err(de::Error::unknown_variant(&index.to_string())),
+            };
+        }
+        let first = match self.de.first.take() {
+            Some(first) => first,
+            None => try!(self.de.read_u8()),
+        };
+        if first >> 5 != 0 {
+            self.de.first = Some(first);
+            return Deserialize::deserialize(self.de);
+        }
+        let index = try!(self.de.parse_additional_information(first)).unwrap_or(!0);
+        match self.variants.get(index as usize) {
+            Some(variant) => V::deserialize(&mut variant.into_deserializer()),
+            None => Err(de::Error::unknown_variant(&index.to_string())),
+        }
+    }
+
+    fn visit_unit(&mut self) -> Result<()> {
+        if self.map {
+            try!(<()>::deserialize(self.de));
+        } else {
+            try!(self.expect_items(0));
+        }
+        self.end()
+    }
+
+    fn visit_newtype<T: Deserialize>(&mut self) -> Result<T> {
+        try!(self.expect_items(1));
+        let value = try!(Deserialize::deserialize(self.de));
+        try!(self.end());
+        Ok(value)
+    }
+
+    fn visit_tuple<V: Visitor>(&mut self, len: usize, mut visitor: V) -> Result<V::Value> {
+        if self.map {
+            let value = try!(de::Deserializer::deserialize_tuple(self.de, len, visitor));
+            try!(self.end());
+            return Ok(value);
+        }
+        try!(self.expect_items(len));
+        let value = try!(visitor.visit_seq(CompositeVisitor::new(self.de, Some(len))));
+        try!(self.end());
+        Ok(value)
+    }
+
     fn visit_struct<V: Visitor>(&mut self, _fields: &'static [&'static str], visitor: V)
             -> Result<V::Value> {
-        de::Deserializer::deserialize(self.de, visitor)
+        try!(self.expect_items(1));
+        let value = try!(de::Deserializer::deserialize(self.de, visitor));
+        try!(self.end());
+        Ok(value)
+    }
+}
+
+/// A function decoding the value inside of a tag, see `decode_by_tag`.
+pub type TagDecoder = fn(&mut Deserializer<&[u8]>) -> Result<Box<Any>>;
+
+/// Decodes a tagged value with the decoder registered for its tag.
+///
+/// The decoder is called with the deserializer positioned at the value inside of the tag. A value
+/// without a tag is a syntax error, a tag without a decoder results in `Error::UnknownTag`.
+pub fn decode_by_tag(v: &[u8], registry: &HashMap<u64, TagDecoder>) -> Result<Box<Any>> {
+    let mut deserializer = Deserializer::new(v);
+    let first = try!(deserializer.read_u8());
+    if first >> 5 != 6 {
+        return Err(Error::Syntax);
+    }
+    let tag = try!(try!(deserializer.parse_additional_information(first)).ok_or(Error::Syntax));
+    let decoder = try!(registry.get(&tag).ok_or(Error::UnknownTag(tag)));
+    let value = try!(decoder(&mut deserializer));
+    try!(deserializer.end());
+    Ok(value)
+}
+
+/// A function decoding the body of a frame, see `read_typed_frame`.
+pub type FrameDecoder = fn(&mut Deserializer<&[u8]>) -> Result<Box<Any>>;
+
+/// Reads a frame of a message stream and decodes its body with the decoder for its type.
+///
+/// A frame is a byte giving the type of the message, the length of the body as a big endian
+/// `u32` and the body, a single CBOR value. `dispatch` returns the decoder for a type or `None`
+/// for types that are not known, which are an `Error::UnknownFrameType`. Returns `None` if the
+/// reader is at its end before the frame starts.
+pub fn read_typed_frame<R, F>(mut reader: R, dispatch: F) -> Result<Option<(u8, Box<Any>)>>
+    where R: Read,
+          F: Fn(u8) -> Option<FrameDecoder>,
+{
+    let mut kind = [0; 1];
+    if try!(reader.read(&mut kind)) == 0 {
+        return Ok(None);
+    }
+    let kind = kind[0];
+    let len = try!(reader.read_u32::<BigEndian>());
+    let decoder = try!(dispatch(kind).ok_or(Error::UnknownFrameType(kind)));
+    let mut body = Vec::new();
+    if try!(reader.take(len as u64).read_to_end(&mut body)) != len as usize {
+        return Err(Error::Eof);
+    }
+    let mut deserializer = Deserializer::new(&body[..]);
+    let value = try!(decoder(&mut deserializer));
+    try!(deserializer.end());
+    Ok(Some((kind, value)))
+}
+
+/// An iterator over the values of a CBOR sequence (RFC 8742).
+///
+/// The values follow each other without any framing, the iterator ends at the end of the input
+/// or after the first error. Bytes allowed with `Deserializer::allow_padding` are skipped between
+/// the values.
+pub struct StreamDeserializer<R: Read, T> {
+    de: Deserializer<R>,
+    failed: bool,
+    output: PhantomData<T>,
+}
+
+impl<R: Read, T: Deserialize> StreamDeserializer<R, T> {
+    /// Creates an iterator decoding the values read by `de`.
+    #[inline]
+    pub fn new(de: Deserializer<R>) -> StreamDeserializer<R, T> {
+        StreamDeserializer {
+            de: de,
+            failed: false,
+            output: PhantomData,
+        }
+    }
+}
+
+impl<R: Read, T: Deserialize> Iterator for StreamDeserializer<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.failed {
+            return None;
+        }
+        let mut byte = [0; 1];
+        let value = loop {
+            match self.de.read(&mut byte) {
+                Ok(0) => return None,
+                Ok(_) if Some(byte[0]) == self.de.padding => {}
+                Ok(_) => {
+                    self.de.first = Some(byte[0]);
+                    break T::deserialize(&mut self.de);
+                }
+                Err(e) => break Err(From::from(e)),
+            }
+        };
+        self.failed = value.is_err();
+        Some(value)
     }
 }
 
@@ -349,8 +1937,158 @@ pub fn from_reader<T: Deserialize, R: Read>(reader: R) -> Result<T> {
     Ok(value)
 }
 
+/// Decodes a CBOR value from a `std::io::Read` after checking that it starts with `preamble`.
+///
+/// This is the counterpart of `ser::Serializer::with_preamble`, input that does not start with
+/// the preamble is rejected with `Error::BadMagic`.
+pub fn from_reader_with_preamble<T: Deserialize, R: Read>(mut reader: R, preamble: &[u8]) -> Result<T> {
+    let mut magic = vec![0; preamble.len()];
+    let mut len = 0;
+    while len < magic.len() {
+        match try!(reader.read(&mut magic[len..])) {
+            0 => break,
+            n => len += n,
+        }
+    }
+    if magic != preamble {
+        return Err(Error::BadMagic);
+    }
+    from_reader(reader)
+}
+
+/// Decodes a CBOR value from a `&[u8]` slice, skipping map entries that fail to decode.
+///
+/// Returns the value if it could be decoded together with the errors of all skipped entries and
+/// the error that stopped decoding, see `Deserializer::collect_errors`.
+pub fn collect_errors<T: Deserialize>(v: &[u8]) -> (Option<T>, Vec<Error>) {
+    let mut de = Deserializer::from_slice(v).collect_errors(true);
+    let result = T::deserialize(&mut de);
+    let end = result.is_ok();
+    let mut errors = de.take_errors();
+    let value = match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+    if end {
+        if let Err(e) = de.end() {
+            errors.push(e);
+        }
+    }
+    (value, errors)
+}
+
 /// Decodes a CBOR value from a `&[u8]` slice.
 #[inline]
 pub fn from_slice<T: Deserialize>(v: &[u8]) -> Result<T> {
-    from_reader(v)
+    let mut de = Deserializer::from_slice(v);
+    let value = try!(Deserialize::deserialize(&mut de));
+    try!(de.end());
+    Ok(value)
+}
+
+/// Decodes the value of the entry with the text key `key` in the map held by `v`.
+///
+/// The values of other entries are skipped and decoding stops at the matching entry, so the rest
+/// of the map is not looked at. Returns `None` if the map has no such entry.
+pub fn extract_field<T: Deserialize>(v: &[u8], key: &str) -> Result<Option<T>> {
+    let mut deserializer = Deserializer::new(v);
+    let first = try!(deserializer.read_u8());
+    if first >> 5 != 5 {
+        return Err(de::Error::invalid_type(de::Type::Map));
+    }
+    let mut remaining = try!(deserializer.parse_size_information(first));
+    loop {
+        match remaining {
+            Some(0) => return Ok(None),
+            Some(ref mut n) => *n -= 1,
+            None => {}
+        }
+        let first = try!(deserializer.read_u8());
+        if first == 0xff && remaining.is_none() {
+            return Ok(None);
+        }
+        deserializer.first = Some(first);
+        if first >> 5 != 3 {
+            try!(deserializer.skip_value());
+        } else if try!(String::deserialize(&mut deserializer)) == key {
+            return Deserialize::deserialize(&mut deserializer).map(Some);
+        }
+        try!(deserializer.skip_value());
+    }
+}
+
+/// Decodes the map held by `v` into a list of its keys and values.
+///
+/// The entries are kept in the order they were encoded, including entries with duplicate keys.
+pub fn decode_map_entries(v: &[u8]) -> Result<Vec<(Value, Value)>> {
+    let mut deserializer = Deserializer::from_slice(v);
+    let first = try!(deserializer.read_u8());
+    if first >> 5 != 5 {
+        return Err(de::Error::invalid_type(de::Type::Map));
+    }
+    let len = try!(deserializer.parse_size_information(first));
+    let mut entries = Vec::new();
+    loop {
+        match len {
+            Some(n) if entries.len() == n => break,
+            Some(_) => {}
+            None => {
+                let first = try!(deserializer.read_u8());
+                if first == 0xff {
+                    break;
+                }
+                deserializer.first = Some(first);
+            }
+        }
+        let key = try!(Value::deserialize(&mut deserializer));
+        let value = try!(Value::deserialize(&mut deserializer));
+        entries.push((key, value));
+    }
+    try!(deserializer.end());
+    Ok(entries)
+}
+
+/// Decodes a `Value` from a `&[u8]` slice together with an annotation of how it was encoded.
+///
+/// The annotation records the width of every integer, float, length and tag and which strings and
+/// containers have an indefinite length, enough to write the value again byte by byte.
+/// `Deserializer::deserialize_annotated` does the same with limits.
+pub fn from_slice_annotated(v: &[u8]) -> Result<(Value, Annotation)> {
+    let mut de = Deserializer::from_slice(v);
+    let value = try!(de.deserialize_annotated());
+    try!(de.end());
+    Ok(value)
+}
+
+/// Decodes a CBOR value from a `&[u8]` slice and tells whether the input was canonical.
+///
+/// Input is canonical if all integers and lengths use their shortest form, all strings and
+/// containers have a definite length and the keys of every map are sorted bytewise by their
+/// encoding without duplicates. A NaN has to be the half precision float `0xf9 0x7e 0x00` and
+/// bignums have to be too large for a regular integer.
+/// Non-canonical input is not an error.
+///
+/// Keys are only compared by their encoding, so `0.0` and `-0.0` are different keys.
+pub fn from_slice_checked<T: Deserialize>(v: &[u8]) -> Result<(T, bool)> {
+    let mut de = Deserializer::from_slice(v);
+    de.canonical = Some(true);
+    let value = try!(Deserialize::deserialize(&mut de));
+    try!(de.end());
+    Ok((value, de.canonical == Some(true)))
+}
+
+/// Decodes a CBOR value from a `&[u8]` slice and also returns the bytes it was decoded from.
+///
+/// The returned slice is the prefix of `v` that holds the value. As with `from_slice` there must
+/// be no trailing bytes after it.
+#[inline]
+pub fn from_slice_with_bytes<T: Deserialize>(v: &[u8]) -> Result<(T, &[u8])> {
+    let mut rest = v;
+    let value = try!(Deserialize::deserialize(&mut Deserializer::new(&mut rest)));
+    let len = v.len() - rest.len();
+    try!(Deserializer::from_slice(rest).end());
+    Ok((value, &v[..len]))
 }
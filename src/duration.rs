@@ -0,0 +1,89 @@
+//! (De)serialization of `std::time::Duration`.
+//!
+//! The functions of this module can be used with `#[serde(serialize_with, deserialize_with)]`.
+//! A duration is written as an array `[seconds, nanoseconds]` or, with `serialize_f64`, as a
+//! number of seconds. Decoding accepts both forms as well as integer seconds. Tags such as 1002
+//! around the value are ignored.
+
+use std::time::Duration;
+
+use serde::de::{self, Deserialize, Deserializer, SeqVisitor, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// Serializes a duration as an array of its seconds and nanoseconds.
+pub fn serialize<S: Serializer>(duration: &Duration, serializer: &mut S) -> Result<(), S::Error> {
+    (duration.as_secs(), duration.subsec_nanos()).serialize(serializer)
+}
+
+/// Serializes a duration as a floating point number of seconds.
+pub fn serialize_f64<S: Serializer>(duration: &Duration, serializer: &mut S) -> Result<(), S::Error> {
+    let secs = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / NANOS_PER_SEC as f64;
+    serializer.serialize_f64(secs)
+}
+
+/// Deserializes a duration from seconds or an array of seconds and nanoseconds.
+///
+/// Negative and non-finite values are rejected.
+pub fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Duration, D::Error> {
+    DurationValue::deserialize(deserializer).map(|duration| duration.0)
+}
+
+/// Visitors have to produce a `Deserialize` type, which `Duration` is not.
+struct DurationValue(Duration);
+
+impl Deserialize for DurationValue {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<DurationValue, D::Error> {
+        deserializer.deserialize(DurationVisitor)
+    }
+}
+
+struct DurationVisitor;
+
+impl Visitor for DurationVisitor {
+    type Value = DurationValue;
+
+    #[inline]
+    fn visit_u64<E: de::Error>(&mut self, v: u64) -> Result<DurationValue, E> {
+        Ok(DurationValue(Duration::new(v, 0)))
+    }
+
+    #[inline]
+    fn visit_i64<E: de::Error>(&mut self, v: i64) -> Result<DurationValue, E> {
+        if v < 0 {
+            return Err(E::invalid_value("negative duration"));
+        }
+        Ok(DurationValue(Duration::new(v as u64, 0)))
+    }
+
+    #[inline]
+    fn visit_f64<E: de::Error>(&mut self, v: f64) -> Result<DurationValue, E> {
+        if !v.is_finite() || v < 0.0 || v >= ::std::u64::MAX as f64 {
+            return Err(E::invalid_value("duration out of range"));
+        }
+        let nanos = (v.fract() * NANOS_PER_SEC as f64).round() as u32;
+        if nanos == NANOS_PER_SEC {
+            Ok(DurationValue(Duration::new(v as u64 + 1, 0)))
+        } else {
+            Ok(DurationValue(Duration::new(v as u64, nanos)))
+        }
+    }
+
+    #[inline]
+    fn visit_seq<V: SeqVisitor>(&mut self, mut visitor: V) -> Result<DurationValue, V::Error> {
+        let secs: u64 = match try!(visitor.visit()) {
+            Some(secs) => secs,
+            None => return Err(de::Error::invalid_length(0)),
+        };
+        let nanos: u32 = match try!(visitor.visit()) {
+            Some(nanos) => nanos,
+            None => return Err(de::Error::invalid_length(1)),
+        };
+        try!(visitor.end());
+        if nanos >= NANOS_PER_SEC {
+            return Err(de::Error::invalid_value("nanoseconds out of range"));
+        }
+        Ok(DurationValue(Duration::new(secs, nanos)))
+    }
+}
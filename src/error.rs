@@ -18,14 +18,49 @@ pub enum Error {
     Io(io::Error),
     /// Some error occured while converting a string.
     FromUtf8(FromUtf8Error),
+    /// A text string is not valid UTF-8, starting at the given byte offset in the input.
+    InvalidUtf8(usize),
     /// A custom error provided by serde occured.
     Custom(String),
     /// The data source contains not enough bytes to parse a value.
     Eof,
     /// Break stop code encountered.
     StopCode,
+    /// The data source does not start with the expected preamble.
+    BadMagic,
     /// The data source contains trailing bytes after all values were read.
-    TrailingBytes,
+    TrailingBytes {
+        /// The major type of the first trailing byte.
+        next_major: u8,
+        /// The number of trailing bytes, `None` if the length of the input is not known.
+        remaining: Option<usize>,
+    },
+    /// An array or map holds more items than were read.
+    TrailingItems,
+    /// Arrays, maps and tags are nested deeper than allowed.
+    DepthLimitExceeded,
+    /// The data source contains more arrays and maps of indefinite length than allowed.
+    TooManyIndefiniteContainers,
+    /// The data source contains more array elements and map entries than allowed.
+    TooManyItems,
+    /// A string of indefinite length consists of more chunks than allowed.
+    TooManyChunks,
+    /// A map has more entries than allowed.
+    TooManyMapEntries,
+    /// Decoding was aborted by the progress callback.
+    Aborted,
+    /// No decoder is known for the tag of a value.
+    UnknownTag(u64),
+    /// No decoder is known for the type of a frame.
+    UnknownFrameType(u8),
+    /// A tag directly repeats the tag before it.
+    DuplicateTag(u64),
+    /// Decoding the element of an array at the given index failed.
+    AtIndex(usize, Box<Error>),
+    /// A NaN is not encoded as the canonical half precision NaN.
+    NonCanonicalNaN,
+    /// The input is not encoded canonically.
+    NonCanonical,
     #[doc(hidden)]
     __Nonexhaustive,
 }
@@ -36,10 +71,25 @@ impl StdError for Error {
             Error::Syntax => "syntax error",
             Error::Io(ref error) => StdError::description(error),
             Error::FromUtf8(ref error) => error.description(),
+            Error::InvalidUtf8(_) => "invalid UTF-8",
             Error::Custom(ref s) => s,
             Error::Eof => "unexpected end of file",
             Error::StopCode => "unexpected stop code",
-            Error::TrailingBytes => "unexpected trailing bytes",
+            Error::BadMagic => "bad magic",
+            Error::TrailingBytes { .. } => "unexpected trailing bytes",
+            Error::TrailingItems => "unexpected trailing items",
+            Error::DepthLimitExceeded => "nesting depth limit exceeded",
+            Error::TooManyIndefiniteContainers => "too many containers of indefinite length",
+            Error::TooManyItems => "too many items",
+            Error::TooManyChunks => "too many chunks",
+            Error::TooManyMapEntries => "too many map entries",
+            Error::Aborted => "decoding aborted",
+            Error::UnknownTag(_) => "unknown tag",
+            Error::UnknownFrameType(_) => "unknown frame type",
+            Error::DuplicateTag(_) => "duplicate tag",
+            Error::NonCanonicalNaN => "non-canonical NaN",
+            Error::NonCanonical => "non-canonical encoding",
+            Error::AtIndex(_, ref error) => error.description(),
             Error::__Nonexhaustive => unreachable!(),
         }
     }
@@ -48,6 +98,7 @@ impl StdError for Error {
         match *self {
             Error::Io(ref error) => Some(error),
             Error::FromUtf8(ref error) => Some(error),
+            Error::AtIndex(_, ref error) => Some(&**error),
             _ => None,
         }
     }
@@ -57,6 +108,15 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Custom(ref s) => write!(f, "custom error: {}", s),
+            Error::TrailingBytes { next_major, remaining: Some(remaining) } => {
+                write!(f, "unexpected trailing bytes: {} bytes starting with major type {}",
+                       remaining, next_major)
+            }
+            Error::UnknownTag(tag) => write!(f, "unknown tag {}", tag),
+            Error::UnknownFrameType(kind) => write!(f, "unknown frame type {}", kind),
+            Error::DuplicateTag(tag) => write!(f, "duplicate tag {}", tag),
+            Error::InvalidUtf8(offset) => write!(f, "invalid UTF-8 at offset {}", offset),
+            Error::AtIndex(index, ref error) => write!(f, "at index {}: {}", index, error),
             _ => f.write_str(self.description())
         }
     }
@@ -0,0 +1,142 @@
+//! Decoding of floats into fixed-point numbers.
+//!
+//! `I8F8`, `I16F16` and `I32F32` are signed fixed-point numbers with the given number of integer
+//! and fractional bits. Their bits are laid out like the types of the same name in the `fixed`
+//! crate, `fixed::types::I16F16::from_bits(value.to_bits())` converts between the two.
+//!
+//! Floats are converted from their bits with integer arithmetic only, half and single precision
+//! floats never pass through an `f64`. The result is rounded to the nearest fixed-point number,
+//! ties to the one with an even last bit. NaN, infinities and numbers out of the range of the type
+//! are errors. Integers are accepted as well.
+//!
+//! This module is only available with the `fixed_point` feature.
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+macro_rules! fixed_types {
+    ($($(#[$attr:meta])* $name:ident($bits:ident, $frac:expr);)*) => {$(
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name($bits);
+
+        impl $name {
+            /// The number of fractional bits.
+            pub const FRAC_BITS: u32 = $frac;
+
+            /// Creates a number from its bits, the value times 2 to the power of `FRAC_BITS`.
+            #[inline]
+            pub fn from_bits(bits: $bits) -> $name {
+                $name(bits)
+            }
+
+            /// Returns the bits of the number.
+            #[inline]
+            pub fn to_bits(self) -> $bits {
+                self.0
+            }
+        }
+
+        impl Deserialize for $name {
+            #[inline]
+            fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<$name, D::Error> {
+                let visitor = FixedVisitor {
+                    frac_bits: $frac,
+                    min: i64::from($bits::MIN),
+                    max: i64::from($bits::MAX),
+                };
+                deserializer.deserialize_f64(visitor).map(|bits| $name(bits as $bits))
+            }
+        }
+    )*}
+}
+
+fixed_types! {
+    /// A fixed-point number with 8 integer and 8 fractional bits.
+    I8F8(i16, 8);
+    /// A fixed-point number with 16 integer and 16 fractional bits.
+    I16F16(i32, 16);
+    /// A fixed-point number with 32 integer and 32 fractional bits.
+    I32F32(i64, 32);
+}
+
+/// Produces the bits of a fixed-point number within `min` and `max`.
+struct FixedVisitor {
+    frac_bits: u32,
+    min: i64,
+    max: i64,
+}
+
+impl FixedVisitor {
+    /// Converts `(-1)^negative * mantissa * 2^exponent`, rounding half to even.
+    fn convert<E>(&self, negative: bool, mantissa: u64, exponent: i32) -> Result<i64, E>
+        where E: de::Error
+    {
+        let shift = exponent + self.frac_bits as i32;
+        let magnitude = if mantissa == 0 {
+            0
+        } else if shift >= 0 {
+            // Anything shifted past bit 64 is out of range for every type.
+            if shift > mantissa.leading_zeros() as i32 {
+                return Err(E::invalid_value("fixed-point number out of range"));
+            }
+            (mantissa as u128) << shift
+        } else {
+            let shift = -shift as u32;
+            if shift > 64 {
+                0
+            } else {
+                let quotient = if shift == 64 { 0 } else { mantissa >> shift };
+                let remainder = mantissa as u128 & ((1 << shift) - 1);
+                let half = 1 << (shift - 1);
+                if remainder > half || remainder == half && quotient & 1 == 1 {
+                    quotient as u128 + 1
+                } else {
+                    quotient as u128
+                }
+            }
+        };
+        let bits = if negative { -(magnitude as i128) } else { magnitude as i128 };
+        if bits < self.min as i128 || bits > self.max as i128 {
+            return Err(E::invalid_value("fixed-point number out of range"));
+        }
+        Ok(bits as i64)
+    }
+}
+
+impl Visitor for FixedVisitor {
+    type Value = i64;
+
+    #[inline]
+    fn visit_u64<E: de::Error>(&mut self, v: u64) -> Result<i64, E> {
+        self.convert(false, v, 0)
+    }
+
+    #[inline]
+    fn visit_i64<E: de::Error>(&mut self, v: i64) -> Result<i64, E> {
+        self.convert(v < 0, v.wrapping_abs() as u64, 0)
+    }
+
+    #[inline]
+    fn visit_f32<E: de::Error>(&mut self, v: f32) -> Result<i64, E> {
+        let bits = v.to_bits();
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let fraction = (bits & 0x7f_ffff) as u64;
+        match exponent {
+            0xff => Err(E::invalid_value("not a finite number")),
+            0 => self.convert(bits >> 31 == 1, fraction, -149),
+            _ => self.convert(bits >> 31 == 1, fraction | 1 << 23, exponent - 150),
+        }
+    }
+
+    #[inline]
+    fn visit_f64<E: de::Error>(&mut self, v: f64) -> Result<i64, E> {
+        let bits = v.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i32;
+        let fraction = bits & 0xf_ffff_ffff_ffff;
+        match exponent {
+            0x7ff => Err(E::invalid_value("not a finite number")),
+            0 => self.convert(bits >> 63 == 1, fraction, -1074),
+            _ => self.convert(bits >> 63 == 1, fraction | 1 << 52, exponent - 1075),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Structs encoded as maps with integer keys.
+//!
+//! Formats like COSE and CWT identify the fields of a map by integer labels instead of names.
+//! The `int_labeled!` macro declares a struct together with the label of each field and
+//! implements `Serialize` and `Deserialize` for it:
+//!
+//! ```rust
+//! #[macro_use]
+//! extern crate serde_cbor;
+//!
+//! int_labeled! {
+//!     #[derive(Debug, PartialEq)]
+//!     /// The claims of a token.
+//!     pub struct Claims {
+//!         /// The issuer.
+//!         pub iss: String = 1,
+//!         exp: Option<u64> = 4,
+//!         note: Option<String> = -7,
+//!     }
+//! }
+//!
+//! # fn main() {
+//! // {1: "me", -7: "hi"}
+//! let claims: Claims = serde_cbor::from_slice(b"\xa2\x01\x62me\x26\x62hi").unwrap();
+//! assert_eq!(claims, Claims { iss: "me".to_owned(), exp: None, note: Some("hi".to_owned()) });
+//! # }
+//! ```
+//!
+//! Entries with unknown labels or keys that are not integers are skipped. A missing entry
+//! decodes as `None` for fields of type `Option`, for other fields it is an error. When
+//! serializing fields that are `None` are left out like absent claims in CWT. The struct and its
+//! fields keep the visibility they are declared with.
+
+use std::marker::PhantomData;
+
+use serde::{de, ser};
+
+use super::error::Error;
+use super::value::Value;
+
+#[doc(hidden)]
+pub use serde::de::{Deserialize, Deserializer, MapVisitor as DeMapVisitor, Visitor};
+#[doc(hidden)]
+pub use serde::de::Error as DeError;
+#[doc(hidden)]
+pub use serde::de::impls::IgnoredAny;
+#[doc(hidden)]
+pub use serde::ser::{MapVisitor as SerMapVisitor, Serialize, Serializer};
+
+/// Returns the integer label of a map key, `None` for keys of other types.
+#[doc(hidden)]
+#[inline]
+pub fn label(key: &Value) -> Option<i64> {
+    match *key {
+        Value::U64(n) if n <= ::std::i64::MAX as u64 => Some(n as i64),
+        Value::I64(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// Produces the value of a field without an entry, `None` for an `Option`.
+#[doc(hidden)]
+#[inline]
+pub fn missing_field<T: Deserialize, E: de::Error>(field: &'static str) -> Result<T, E> {
+    struct MissingField<E> {
+        field: &'static str,
+        error: PhantomData<E>,
+    }
+
+    impl<E: de::Error> de::Deserializer for MissingField<E> {
+        type Error = E;
+
+        #[inline]
+        fn deserialize<V: de::Visitor>(&mut self, _visitor: V) -> Result<V::Value, E> {
+            Err(E::missing_field(self.field))
+        }
+
+        #[inline]
+        fn deserialize_option<V: de::Visitor>(&mut self, mut visitor: V) -> Result<V::Value, E> {
+            visitor.visit_none()
+        }
+    }
+
+    T::deserialize(&mut MissingField { field: field, error: PhantomData })
+}
+
+/// Tells whether a field is `None`, such fields are not written.
+#[doc(hidden)]
+#[inline]
+pub fn is_none<T: Serialize>(value: &T) -> bool {
+    struct NoneProbe(bool);
+
+    impl ser::Serializer for NoneProbe {
+        type Error = Error;
+
+        fn serialize_bool(&mut self, _: bool) -> Result<(), Error> { Ok(()) }
+        fn serialize_i64(&mut self, _: i64) -> Result<(), Error> { Ok(()) }
+        fn serialize_u64(&mut self, _: u64) -> Result<(), Error> { Ok(()) }
+        fn serialize_f64(&mut self, _: f64) -> Result<(), Error> { Ok(()) }
+        fn serialize_str(&mut self, _: &str) -> Result<(), Error> { Ok(()) }
+        fn serialize_unit(&mut self) -> Result<(), Error> { Ok(()) }
+
+        fn serialize_none(&mut self) -> Result<(), Error> {
+            self.0 = true;
+            Ok(())
+        }
+
+        fn serialize_some<V: Serialize>(&mut self, _: V) -> Result<(), Error> { Ok(()) }
+        fn serialize_seq<V: ser::SeqVisitor>(&mut self, _: V) -> Result<(), Error> { Ok(()) }
+        fn serialize_seq_elt<T: Serialize>(&mut self, _: T) -> Result<(), Error> { Ok(()) }
+        fn serialize_map<V: ser::MapVisitor>(&mut self, _: V) -> Result<(), Error> { Ok(()) }
+
+        fn serialize_map_elt<K: Serialize, V: Serialize>(&mut self, _: K, _: V)
+                                                         -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    let mut probe = NoneProbe(false);
+    value.serialize(&mut probe).is_ok() && probe.0
+}
+
+/// Declares a struct that is encoded as a map with integer keys, see the `int_labeled` module.
+#[macro_export]
+macro_rules! int_labeled {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $ty:ty = $label:expr),* $(,)*
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $(
+                $(#[$field_attr])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::int_labeled::Deserialize for $name {
+            fn deserialize<D>(deserializer: &mut D) -> ::std::result::Result<$name, D::Error>
+                where D: $crate::int_labeled::Deserializer,
+            {
+                struct LabeledVisitor;
+
+                impl $crate::int_labeled::Visitor for LabeledVisitor {
+                    type Value = $name;
+
+                    fn visit_map<V>(&mut self, mut visitor: V) -> ::std::result::Result<$name, V::Error>
+                        where V: $crate::int_labeled::DeMapVisitor,
+                    {
+                        $(let mut $field = None;)*
+                        while let Some(key) = try!(visitor.visit_key::<$crate::Value>()) {
+                            let label = $crate::int_labeled::label(&key);
+                            $(
+                                if label == Some($label) {
+                                    if $field.is_some() {
+                                        return Err(<V::Error as $crate::int_labeled::DeError>::duplicate_field(
+                                            stringify!($field)));
+                                    }
+                                    $field = Some(try!(visitor.visit_value()));
+                                    continue;
+                                }
+                            )*
+                            try!(visitor.visit_value::<$crate::int_labeled::IgnoredAny>());
+                        }
+                        try!(visitor.end());
+                        Ok($name {
+                            $(
+                                $field: match $field {
+                                    Some(value) => value,
+                                    None => try!($crate::int_labeled::missing_field(stringify!($field))),
+                                },
+                            )*
+                        })
+                    }
+                }
+
+                deserializer.deserialize_map(LabeledVisitor)
+            }
+        }
+
+        impl $crate::int_labeled::Serialize for $name {
+            fn serialize<S>(&self, serializer: &mut S) -> ::std::result::Result<(), S::Error>
+                where S: $crate::int_labeled::Serializer,
+            {
+                struct LabeledVisitor<'a>(&'a $name, bool);
+
+                impl<'a> $crate::int_labeled::SerMapVisitor for LabeledVisitor<'a> {
+                    fn visit<S>(&mut self, serializer: &mut S) -> ::std::result::Result<Option<()>, S::Error>
+                        where S: $crate::int_labeled::Serializer,
+                    {
+                        if self.1 {
+                            return Ok(None);
+                        }
+                        self.1 = true;
+                        $(
+                            if !$crate::int_labeled::is_none(&(self.0).$field) {
+                                try!(serializer.serialize_map_elt($label as i64, &(self.0).$field));
+                            }
+                        )*
+                        Ok(Some(()))
+                    }
+
+                    fn len(&self) -> Option<usize> {
+                        let mut len = 0;
+                        $(
+                            if !$crate::int_labeled::is_none(&(self.0).$field) {
+                                len += 1;
+                            }
+                        )*
+                        Some(len)
+                    }
+                }
+
+                serializer.serialize_map(LabeledVisitor(self, false))
+            }
+        }
+    };
+}
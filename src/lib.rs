@@ -120,9 +120,14 @@ extern crate serde;
 pub use de::{from_slice, from_reader};
 pub use error::{Error, Result};
 pub use ser::{to_vec, to_writer, to_vec_sd, to_writer_sd};
-pub use value::{Value, ObjectKey};
+pub use value::{Value, ObjectKey, RawValue, Annotation, Number};
 
 pub mod de;
+pub mod duration;
 pub mod error;
+#[cfg(feature = "fixed_point")]
+pub mod fixed;
+pub mod int_labeled;
 pub mod ser;
 pub mod value;
+
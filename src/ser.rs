@@ -1,22 +1,293 @@
 //! CBOR serializisation.
 
-use std::io::Write;
+use std::io::{self, Write};
+use std::mem;
 
 use byteorder::{BigEndian, WriteBytesExt};
+use serde::de::Deserialize;
 use serde::ser::{self, Serialize, SeqVisitor, MapVisitor};
+use serde::ser::impls::{MapIteratorVisitor, SeqIteratorVisitor};
 
+use super::de::Deserializer;
 use super::error::{Error, Result};
+use super::value::{RAW_VALUE_NAME, TAG_NAME};
+
+/// The size of the argument of an integer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Width {
+    /// One byte.
+    Width1,
+    /// Two bytes.
+    Width2,
+    /// Four bytes.
+    Width4,
+    /// Eight bytes.
+    Width8,
+}
+
+/// How integers are written, see `Serializer::integer_width`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegerWidth {
+    /// The shortest form that holds the integer, this is the default.
+    Shortest,
+    /// Always the given width.
+    Fixed(Width),
+}
+
+/// The settings of a `Serializer`.
+///
+/// The methods correspond to the ones of `Serializer`, which describe them in detail. Options
+/// allow to keep a profile of settings around and to create serializers from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+    canonical: bool,
+    typed_arrays: bool,
+    smallest_float: bool,
+    integer_width: IntegerWidth,
+    chars_as_integers: bool,
+}
+
+impl Options {
+    /// Creates the default settings, a compact but not canonical encoding.
+    #[inline]
+    pub fn new() -> Options {
+        Options {
+            canonical: false,
+            typed_arrays: false,
+            smallest_float: false,
+            integer_width: IntegerWidth::Shortest,
+            chars_as_integers: false,
+        }
+    }
+
+    /// Emits the canonical encoding of values, see `Serializer::canonical`.
+    #[inline]
+    pub fn canonical(mut self) -> Options {
+        self.canonical = true;
+        self
+    }
+
+    /// Emits sequences of numbers as typed arrays, see `Serializer::typed_arrays`.
+    #[inline]
+    pub fn typed_arrays(mut self) -> Options {
+        self.typed_arrays = true;
+        self
+    }
+
+    /// Writes floats in the smallest precision, see `Serializer::prefer_smallest_float`.
+    #[inline]
+    pub fn prefer_smallest_float(mut self) -> Options {
+        self.smallest_float = true;
+        self
+    }
+
+    /// Sets how many bytes integers take up, see `Serializer::integer_width`.
+    #[inline]
+    pub fn integer_width(mut self, width: IntegerWidth) -> Options {
+        self.integer_width = width;
+        self
+    }
+
+    /// Writes chars as their code point, see `Serializer::chars_as_integers`.
+    #[inline]
+    pub fn chars_as_integers(mut self) -> Options {
+        self.chars_as_integers = true;
+        self
+    }
+}
+
+impl Default for Options {
+    #[inline]
+    fn default() -> Options {
+        Options::new()
+    }
+}
 
 /// A structure for serializing Rust values into CBOR.
 pub struct Serializer<W: Write> {
     writer: W,
+    options: Options,
+    number: Option<u64>,
+    raw: bool,
+    tag: bool,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
 }
 
 impl<W: Write> Serializer<W> {
     /// Creates a new CBOR serializer.
     #[inline]
     pub fn new(writer: W) -> Serializer<W> {
-        Serializer { writer: writer }
+        Serializer::with_options(writer, Options::new())
+    }
+
+    /// Creates a new CBOR serializer with the given settings.
+    #[inline]
+    pub fn with_options(writer: W, options: Options) -> Serializer<W> {
+        Serializer {
+            writer: writer,
+            options: options,
+            number: None,
+            raw: false,
+            tag: false,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Emits the canonical encoding of values.
+    ///
+    /// Sequences of unknown length are written with a definite length and map entries are sorted
+    /// bytewise by the encoding of their keys. To do so each map and each sequence of unknown
+    /// length is buffered completely before it is written. Sequences of known length and other
+    /// values are written right away. Keys that compare equal but are encoded differently, like
+    /// `0.0` and `-0.0`, are different keys.
+    #[inline]
+    pub fn canonical(mut self) -> Serializer<W> {
+        self.options = self.options.canonical();
+        self
+    }
+
+    /// Emits sequences of numbers of the same type as typed arrays (RFC 8746).
+    ///
+    /// The elements are packed big endian into a byte string, which is far more compact than an
+    /// array of numbers. Sequences are buffered to find out whether they qualify.
+    #[inline]
+    pub fn typed_arrays(mut self) -> Serializer<W> {
+        self.options = self.options.typed_arrays();
+        self
+    }
+
+    /// Writes floats in the smallest of half, single and double precision that holds them exactly.
+    ///
+    /// A value is written as a half if converting it to half precision and back gives the same
+    /// value, otherwise as a single if that round trip is exact, otherwise as a double. Signed
+    /// zeros keep their sign, infinities are halves and any NaN is written as the half `0x7e00`.
+    #[inline]
+    pub fn prefer_smallest_float(mut self) -> Serializer<W> {
+        self.options = self.options.prefer_smallest_float();
+        self
+    }
+
+    /// Sets how many bytes integers take up.
+    ///
+    /// With a fixed width every integer is written with an argument of that size, integers that
+    /// do not fit are an error. Lengths and tags are still written in their shortest form.
+    #[inline]
+    pub fn integer_width(mut self, width: IntegerWidth) -> Serializer<W> {
+        self.options = self.options.integer_width(width);
+        self
+    }
+
+    /// Writes chars as the unsigned integer of their Unicode scalar value.
+    ///
+    /// By default a char is written as a text string holding just that char. The deserializer
+    /// accepts both for a `char`.
+    #[inline]
+    pub fn chars_as_integers(mut self) -> Serializer<W> {
+        self.options = self.options.chars_as_integers();
+        self
+    }
+
+    /// Writes an application specific magic number before the document.
+    ///
+    /// The bytes are written right away, unchanged. `de::from_reader_with_preamble` checks and
+    /// skips them again.
+    #[inline]
+    pub fn with_preamble(mut self, preamble: &[u8]) -> Result<Serializer<W>> {
+        try!(self.writer.write_all(preamble));
+        Ok(self)
+    }
+
+    /// Creates a serializer with the same settings writing into a buffer.
+    #[inline]
+    fn buffer(&self) -> Serializer<Vec<u8>> {
+        Serializer {
+            writer: Vec::new(),
+            options: self.options,
+            number: None,
+            raw: false,
+            tag: false,
+            entries: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn serialize_buffered<V: SeqVisitor>(&mut self, mut visitor: V) -> Result<(usize, Vec<u8>)> {
+        let mut buffer = self.buffer();
+        let mut len = 0;
+        while let Some(()) = try!(visitor.visit(&mut buffer)) {
+            len += 1;
+        }
+        Ok((len, buffer.writer))
+    }
+
+    /// Writes a sequence as a typed array if all elements are numbers of the same type.
+    fn serialize_typed_array<V: SeqVisitor>(&mut self, mut visitor: V) -> Result<()> {
+        let mut buffer = self.buffer();
+        let mut tag = None;
+        let mut len = 0;
+        let mut typed = true;
+        loop {
+            let start = buffer.writer.len();
+            buffer.number = None;
+            if try!(visitor.visit(&mut buffer)).is_none() {
+                break;
+            }
+            len += 1;
+            let number = match buffer.writer[start] {
+                0x00...0x3f | 0xf9...0xfb => buffer.number.take(),
+                _ => None,
+            };
+            if number.is_none() || tag.is_some() && tag != number {
+                typed = false;
+            }
+            tag = number;
+        }
+        let tag = match tag {
+            Some(tag) if typed => tag,
+            _ => {
+                try!(self.compact_type(4, len as u64));
+                return self.writer.write_all(&buffer.writer).map_err(From::from);
+            }
+        };
+        let mut bytes = Vec::new();
+        let mut elements = &buffer.writer[..];
+        for _ in 0..len {
+            let mut de = Deserializer::new(&mut elements);
+            match tag {
+                64 => try!(bytes.write_u8(try!(u8::deserialize(&mut de)))),
+                65 => try!(bytes.write_u16::<BigEndian>(try!(u16::deserialize(&mut de)))),
+                66 => try!(bytes.write_u32::<BigEndian>(try!(u32::deserialize(&mut de)))),
+                67 => try!(bytes.write_u64::<BigEndian>(try!(u64::deserialize(&mut de)))),
+                72 => try!(bytes.write_i8(try!(i8::deserialize(&mut de)))),
+                73 => try!(bytes.write_i16::<BigEndian>(try!(i16::deserialize(&mut de)))),
+                74 => try!(bytes.write_i32::<BigEndian>(try!(i32::deserialize(&mut de)))),
+                75 => try!(bytes.write_i64::<BigEndian>(try!(i64::deserialize(&mut de)))),
+                81 => try!(bytes.write_f32::<BigEndian>(try!(f32::deserialize(&mut de)))),
+                _ => try!(bytes.write_f64::<BigEndian>(try!(f64::deserialize(&mut de)))),
+            }
+        }
+        try!(self.compact_type(6, tag));
+        try!(self.compact_type(2, bytes.len() as u64));
+        self.writer.write_all(&bytes).map_err(From::from)
+    }
+
+    #[inline]
+    fn serialize_integer(&mut self, major_type: u8, v: u64) -> Result<()> {
+        let width = match self.options.integer_width {
+            IntegerWidth::Shortest => return self.compact_type(major_type, v),
+            IntegerWidth::Fixed(width) => width,
+        };
+        let (info, bytes) = match width {
+            Width::Width1 => (24, 1),
+            Width::Width2 => (25, 2),
+            Width::Width4 => (26, 4),
+            Width::Width8 => (27, 8),
+        };
+        if bytes < 8 && v >> (8 * bytes) != 0 {
+            return Err(ser::Error::invalid_value("integer does not fit into the fixed width"));
+        }
+        try!(self.writer.write_u8(major_type << 5 | info));
+        self.writer.write_uint::<BigEndian>(v, bytes).map_err(From::from)
     }
 
     #[inline]
@@ -58,26 +329,79 @@ impl<W: Write> ser::Serializer for Serializer<W> {
             .map_err(From::from)
     }
     #[inline]
+    fn serialize_i8(&mut self, v: i8) -> Result<()> {
+        try!(self.serialize_i64(v as i64));
+        self.number = Some(72);
+        Ok(())
+    }
+    #[inline]
+    fn serialize_i16(&mut self, v: i16) -> Result<()> {
+        try!(self.serialize_i64(v as i64));
+        self.number = Some(73);
+        Ok(())
+    }
+    #[inline]
+    fn serialize_i32(&mut self, v: i32) -> Result<()> {
+        try!(self.serialize_i64(v as i64));
+        self.number = Some(74);
+        Ok(())
+    }
+    #[inline]
     fn serialize_i64(&mut self, v: i64) -> Result<()> {
+        self.number = Some(75);
         if v >= 0 {
-            self.serialize_u64(v as u64)
+            self.serialize_integer(0, v as u64)
         } else {
-            self.compact_type(1, (-v) as u64 - 1)
+            self.serialize_integer(1, !v as u64)
         }
     }
     #[inline]
+    fn serialize_u8(&mut self, v: u8) -> Result<()> {
+        try!(self.serialize_u64(v as u64));
+        self.number = Some(64);
+        Ok(())
+    }
+    #[inline]
+    fn serialize_u16(&mut self, v: u16) -> Result<()> {
+        try!(self.serialize_u64(v as u64));
+        self.number = Some(65);
+        Ok(())
+    }
+    #[inline]
+    fn serialize_u32(&mut self, v: u32) -> Result<()> {
+        try!(self.serialize_u64(v as u64));
+        self.number = Some(66);
+        Ok(())
+    }
+    #[inline]
     fn serialize_u64(&mut self, v: u64) -> Result<()> {
-        self.compact_type(0, v)
+        if mem::replace(&mut self.tag, false) {
+            return self.compact_type(6, v);
+        }
+        self.number = Some(67);
+        self.serialize_integer(0, v)
+    }
+    #[inline]
+    fn serialize_f32(&mut self, v: f32) -> Result<()> {
+        try!(self.serialize_f64(v as f64));
+        self.number = Some(81);
+        Ok(())
     }
     #[inline]
     fn serialize_f64(&mut self, v: f64) -> Result<()> {
-        // TODO: Encode to f16
+        self.number = Some(82);
+        let half = if self.options.smallest_float { to_f16(v) } else { None };
         if v.is_infinite() && v.is_sign_positive() {
             self.writer.write_all(&[0xf9, 0x7c, 0x00]).map_err(From::from)
         } else if v.is_infinite() && v.is_sign_negative() {
             self.writer.write_all(&[0xf9, 0xfc, 0x00]).map_err(From::from)
         } else if v.is_nan() {
             self.writer.write_all(&[0xf9, 0x7e, 0x00]).map_err(From::from)
+        } else if let Some(half) = half {
+            self.writer
+                .write_u8(0xf9)
+                .and_then(|()| self.writer.write_u16::<BigEndian>(half))
+                .map_err(From::from)
         } else if v as f32 as f64 == v {
             self.writer
                 .write_u8(0xfa)
@@ -91,11 +415,26 @@ impl<W: Write> ser::Serializer for Serializer<W> {
         }
     }
     #[inline]
+    fn serialize_char(&mut self, value: char) -> Result<()> {
+        if self.options.chars_as_integers {
+            return self.serialize_integer(0, value as u64);
+        }
+        let mut buf = [0; 4];
+        self.serialize_str(value.encode_utf8(&mut buf))
+    }
+    #[inline]
     fn serialize_str(&mut self, value: &str) -> Result<()> {
         self.compact_type(3, value.len() as u64)
             .and_then(|()| self.writer.write_all(value.as_bytes()).map_err(From::from))
     }
     #[inline]
+    fn serialize_bytes(&mut self, value: &[u8]) -> Result<()> {
+        if mem::replace(&mut self.raw, false) {
+            return self.writer.write_all(value).map_err(From::from);
+        }
+        self.serialize_seq(SeqIteratorVisitor::new(value.iter(), Some(value.len())))
+    }
+    #[inline]
     fn serialize_unit(&mut self) -> Result<()> {
         self.writer.write_u8(0xf6).map_err(From::from)
     }
@@ -107,13 +446,22 @@ impl<W: Write> ser::Serializer for Serializer<W> {
     fn serialize_some<V>(&mut self, value: V) -> Result<()>
         where V: Serialize
     {
-        value.serialize(self)
+        try!(value.serialize(self));
+        // Only bare numbers go into typed arrays, `Option<u8>` decodes from no typed array.
+        self.number = None;
+        Ok(())
     }
     #[inline]
     fn serialize_seq<V>(&mut self, mut visitor: V) -> Result<()>
         where V: SeqVisitor
     {
-        if let Some(len) = visitor.len() {
+        if self.options.typed_arrays {
+            self.serialize_typed_array(visitor)
+        } else if visitor.len().is_none() && self.options.canonical {
+            let (len, buffer) = try!(self.serialize_buffered(visitor));
+            try!(self.compact_type(4, len as u64));
+            self.writer.write_all(&buffer).map_err(From::from)
+        } else if let Some(len) = visitor.len() {
             try!(self.compact_type(4, len as u64));
             while let Some(()) = try!(visitor.visit(self)) {
             }
@@ -135,7 +483,19 @@ impl<W: Write> ser::Serializer for Serializer<W> {
     fn serialize_map<V>(&mut self, mut visitor: V) -> Result<()>
         where V: MapVisitor
     {
-        if let Some(len) = visitor.len() {
+        if self.options.canonical {
+            let outer = mem::take(&mut self.entries);
+            while let Some(()) = try!(visitor.visit(self)) {
+            }
+            let mut entries = mem::replace(&mut self.entries, outer);
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            try!(self.compact_type(5, entries.len() as u64));
+            for (key, value) in entries {
+                try!(self.writer.write_all(&key));
+                try!(self.writer.write_all(&value));
+            }
+            Ok(())
+        } else if let Some(len) = visitor.len() {
             try!(self.compact_type(5, len as u64));
             while let Some(()) = try!(visitor.visit(self)) {
             }
@@ -152,7 +512,16 @@ impl<W: Write> ser::Serializer for Serializer<W> {
         where K: Serialize,
               V: Serialize
     {
-        key.serialize(self).and_then(|()| value.serialize(self))
+        if self.options.canonical {
+            let mut key_buffer = self.buffer();
+            try!(key.serialize(&mut key_buffer));
+            let mut value_buffer = self.buffer();
+            try!(value.serialize(&mut value_buffer));
+            self.entries.push((key_buffer.writer, value_buffer.writer));
+            Ok(())
+        } else {
+            key.serialize(self).and_then(|()| value.serialize(self))
+        }
     }
     #[inline]
     fn serialize_unit_variant(&mut self, _name: &'static str,
@@ -162,11 +531,34 @@ impl<W: Write> ser::Serializer for Serializer<W> {
     }
     #[inline]
     fn serialize_newtype_struct<T>(&mut self,
-                                   _name: &'static str,
+                                   name: &'static str,
                                    value: T) -> Result<()>
         where T: ser::Serialize,
     {
-        value.serialize(self)
+        if name == RAW_VALUE_NAME {
+            self.raw = true;
+            let result = value.serialize(self);
+            self.raw = false;
+            return result;
+        }
+        try!(value.serialize(self));
+        self.number = None;
+        Ok(())
+    }
+    #[inline]
+    fn serialize_tuple_struct<V>(&mut self, name: &'static str, mut visitor: V) -> Result<()>
+        where V: SeqVisitor
+    {
+        if name == TAG_NAME {
+            // A tagged `Value` visits the tag number and then the value.
+            self.tag = true;
+            while let Some(()) = try!(visitor.visit(self)) {
+            }
+            self.tag = false;
+            self.number = None;
+            return Ok(());
+        }
+        self.serialize_tuple(visitor)
     }
     #[inline]
     fn serialize_newtype_variant<T>(&mut self, _name: &'static str,
@@ -180,7 +572,12 @@ impl<W: Write> ser::Serializer for Serializer<W> {
     fn serialize_tuple_variant<V>(&mut self, _name: &'static str,
             _variant_index: usize, variant: &'static str, mut visitor: V)
             -> Result<()> where V: SeqVisitor {
-        if let Some(len) = visitor.len() {
+        if visitor.len().is_none() && self.options.canonical {
+            let (len, buffer) = try!(self.serialize_buffered(visitor));
+            try!(self.compact_type(4, len as u64 + 1));
+            try!(self.serialize_str(variant));
+            self.writer.write_all(&buffer).map_err(From::from)
+        } else if let Some(len) = visitor.len() {
             try!(self.compact_type(4, len as u64 + 1));
             try!(self.serialize_str(variant));
             while let Some(()) = try!(visitor.visit(self)) {
@@ -212,6 +609,34 @@ impl<W: Write> ser::Serializer for Serializer<W> {
     }
 }
 
+/// Returns the half precision encoding of `v` if it holds `v` exactly.
+fn to_f16(v: f64) -> Option<u16> {
+    let single = v as f32;
+    if single as f64 != v {
+        return None;
+    }
+    let bits = single.to_bits();
+    let sign = (bits >> 16) as u16 & 0x8000;
+    let exp = (bits >> 23 & 0xff) as i32 - 127;
+    let mant = bits & 0x7f_ffff;
+    if exp == 128 || exp == -127 && mant == 0 {
+        // Infinities and zeros, NaN never gets here.
+        return Some(sign | if exp == 128 { 0x7c00 } else { 0 });
+    }
+    if (-14..=15).contains(&exp) && mant & 0x1fff == 0 {
+        return Some(sign | ((exp + 15) as u16) << 10 | (mant >> 13) as u16);
+    }
+    if (-24..-14).contains(&exp) {
+        // Subnormal halves, the implicit leading bit becomes part of the mantissa.
+        let mant = mant | 0x80_0000;
+        let shift = -exp - 1;
+        if mant & ((1 << shift) - 1) == 0 {
+            return Some(sign | (mant >> shift) as u16);
+        }
+    }
+    None
+}
+
 /// Encodes the specified struct into a writer.
 #[inline]
 pub fn to_writer<W: Write, T: ser::Serialize>(writer: &mut W, value: &T) -> Result<()> {
@@ -242,3 +667,138 @@ pub fn to_vec_sd<T: Serialize>(value: &T) -> Result<Vec<u8>> {
     try!(to_writer_sd(&mut writer, value));
     Ok(writer)
 }
+
+/// Encodes the items of an iterator one after another into a writer, as a CBOR sequence
+/// (RFC 8742).
+#[inline]
+pub fn to_writer_seq<W, I>(writer: &mut W, iter: I) -> Result<()>
+    where W: Write,
+          I: IntoIterator,
+          I::Item: Serialize,
+{
+    let mut ser = Serializer::new(writer);
+    for item in iter {
+        try!(item.serialize(&mut ser));
+    }
+    Ok(())
+}
+
+/// Encodes the items of an iterator one after another into a `Vec<u8>`, as a CBOR sequence
+/// (RFC 8742).
+#[inline]
+pub fn to_vec_seq<I>(iter: I) -> Result<Vec<u8>>
+    where I: IntoIterator,
+          I::Item: Serialize,
+{
+    let mut writer = Vec::new();
+    try!(to_writer_seq(&mut writer, iter));
+    Ok(writer)
+}
+
+/// Writes the pairs of an iterator as the entries of a map, without collecting them first.
+///
+/// If `len` is given a map of definite length is written and the iterator has to yield exactly
+/// `len` pairs, otherwise the map has an indefinite length.
+///
+/// A `len` outside of the size hint of the iterator is rejected before anything is written. Other
+/// mismatches only show once the iterator is exhausted, the map is then already written with the
+/// wrong length and the output has to be discarded.
+pub fn serialize_map_iter<W, I, K, V>(serializer: &mut Serializer<W>, iter: I, len: Option<usize>)
+                                      -> Result<()>
+    where W: Write,
+          I: IntoIterator<Item = (K, V)>,
+          K: Serialize,
+          V: Serialize,
+{
+    let iter = iter.into_iter();
+    if let Some(len) = len {
+        let (lower, upper) = iter.size_hint();
+        if len < lower || len > upper.unwrap_or(len) {
+            return Err(ser::Error::invalid_value(
+                "the iterator yields a different number of pairs than announced"));
+        }
+    }
+    let mut count = 0;
+    {
+        let iter = iter.inspect(|_| count += 1);
+        try!(ser::Serializer::serialize_map(serializer, MapIteratorVisitor::new(iter, len)));
+    }
+    match len {
+        Some(len) if len != count => {
+            Err(ser::Error::invalid_value("the iterator yielded a different number of pairs than announced"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Encodes the pairs of an iterator as a map into a `Vec<u8>`, see `serialize_map_iter`.
+#[inline]
+pub fn to_vec_map_iter<I, K, V>(iter: I, len: Option<usize>) -> Result<Vec<u8>>
+    where I: IntoIterator<Item = (K, V)>,
+          K: Serialize,
+          V: Serialize,
+{
+    let mut writer = Vec::new();
+    try!(serialize_map_iter(&mut Serializer::new(&mut writer), iter, len));
+    Ok(writer)
+}
+
+/// Writes a value as the body of a frame with the given type, see `de::read_typed_frame`.
+pub fn write_typed_frame<W: Write, T: Serialize>(writer: &mut W, kind: u8, value: &T) -> Result<()> {
+    let body = try!(to_vec(value));
+    if body.len() > ::std::u32::MAX as usize {
+        return Err(ser::Error::invalid_value("the frame is too large"));
+    }
+    try!(writer.write_u8(kind));
+    try!(writer.write_u32::<BigEndian>(body.len() as u32));
+    writer.write_all(&body).map_err(From::from)
+}
+
+/// Encodes the specified struct canonically into a writer.
+///
+/// Maps are held back until their entries are sorted and sequences of unknown length until their
+/// length is known, each of them is written out as soon as it is complete. The memory needed is
+/// bounded by the largest such map or sequence including everything nested in it, which can be
+/// the whole value.
+#[inline]
+pub fn to_writer_canonical<W: Write, T: ser::Serialize>(writer: &mut W, value: &T) -> Result<()> {
+    let mut ser = Serializer::new(writer).canonical();
+    value.serialize(&mut ser).map_err(From::from)
+}
+
+/// Encodes the specified struct canonically into a `Vec<u8>`.
+#[inline]
+pub fn to_vec_canonical<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut writer = Vec::new();
+    try!(to_writer_canonical(&mut writer, value));
+    Ok(writer)
+}
+
+/// Encodes the specified struct canonically into a `Vec<u8>` while also feeding the bytes to a
+/// hasher.
+///
+/// The hasher is anything implementing `std::io::Write`, for example the RustCrypto `digest`
+/// hashers. It is returned after the value is written so the caller can finalize it.
+#[inline]
+pub fn to_vec_hashed<T: Serialize, H: Write>(value: &T, hasher: H) -> Result<(Vec<u8>, H)> {
+    struct HashingWriter<H> {
+        bytes: Vec<u8>,
+        hasher: H,
+    }
+
+    impl<H: Write> Write for HashingWriter<H> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            try!(self.hasher.write_all(buf));
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.hasher.flush()
+        }
+    }
+
+    let mut ser = Serializer::new(HashingWriter { bytes: Vec::new(), hasher: hasher }).canonical();
+    try!(value.serialize(&mut ser));
+    Ok((ser.writer.bytes, ser.writer.hasher))
+}
@@ -2,9 +2,13 @@
 
 use std::collections::HashMap;
 
+use serde::bytes::Bytes;
 use serde::de::{self, SeqVisitor};
 use serde::ser;
 
+use super::error;
+use super::ser::{Options, Serializer};
+
 /// An enum over all possible CBOR types.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
@@ -26,6 +30,8 @@ pub enum Value {
     Bool(bool),
     /// Represents the absence of a value or the value undefined.
     Null,
+    /// Represents a tagged value.
+    Tag(u64, Box<Value>),
 }
 
 impl Value {
@@ -209,6 +215,29 @@ impl Value {
             None
         }
     }
+
+    /// Returns true if the value is tagged.
+    pub fn is_tag(&self) -> bool {
+        self.as_tag().is_some()
+    }
+
+    /// If the value is tagged, returns the tag and the tagged value. Returns None otherwise.
+    pub fn as_tag(&self) -> Option<(u64, &Value)> {
+        if let Value::Tag(tag, ref v) = *self {
+            Some((tag, v))
+        } else {
+            None
+        }
+    }
+
+    /// Encodes the value with the given serializer settings.
+    ///
+    /// This allows to write decoded CBOR again in another profile, for example canonically.
+    pub fn encode_with(&self, options: Options) -> error::Result<Vec<u8>> {
+        let mut writer = Vec::new();
+        try!(ser::Serialize::serialize(self, &mut Serializer::with_options(&mut writer, options)));
+        Ok(writer)
+    }
 }
 
 impl de::Deserialize for Value {
@@ -305,9 +334,48 @@ impl de::Deserialize for Value {
             {
                 Ok(Value::F64(v))
             }
+
+            #[inline]
+            fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Value, D::Error>
+                where D: de::Deserializer
+            {
+                deserializer.deserialize_newtype_struct(TAG_NAME, TagVisitor)
+            }
+        }
+
+        /// Receives a tagged value as the pair of the tag and the value.
+        ///
+        /// Other data formats visit newtype structs directly, the value is then untagged.
+        struct TagVisitor;
+
+        impl de::Visitor for TagVisitor {
+            type Value = Value;
+
+            #[inline]
+            fn visit_seq<V>(&mut self, mut visitor: V) -> Result<Value, V::Error>
+                where V: SeqVisitor
+            {
+                let tag = match try!(visitor.visit()) {
+                    Some(tag) => tag,
+                    None => return Err(de::Error::invalid_length(0)),
+                };
+                let value = match try!(visitor.visit()) {
+                    Some(value) => value,
+                    None => return Err(de::Error::invalid_length(1)),
+                };
+                try!(visitor.end());
+                Ok(Value::Tag(tag, Box::new(value)))
+            }
+
+            #[inline]
+            fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<Value, D::Error>
+                where D: de::Deserializer
+            {
+                deserializer.deserialize(ValueVisitor)
+            }
         }
 
-        deserializer.deserialize(ValueVisitor)
+        deserializer.deserialize_newtype_struct(TAG_NAME, ValueVisitor)
     }
 }
 
@@ -327,6 +395,272 @@ impl ser::Serialize for Value {
             Value::F64(v) => serializer.serialize_f64(v),
             Value::Bool(v) => serializer.serialize_bool(v),
             Value::Null => serializer.serialize_unit(),
+            Value::Tag(tag, ref v) => {
+                serializer.serialize_tuple_struct(TAG_NAME, TagSeqVisitor {
+                    tag: tag,
+                    value: v,
+                    state: 0,
+                })
+            }
+        }
+    }
+}
+
+/// Visits the tag number and the value of a tagged value.
+struct TagSeqVisitor<'a> {
+    tag: u64,
+    value: &'a Value,
+    state: u8,
+}
+
+impl<'a> ser::SeqVisitor for TagSeqVisitor<'a> {
+    #[inline]
+    fn visit<S>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error>
+        where S: ser::Serializer
+    {
+        self.state += 1;
+        match self.state {
+            1 => serializer.serialize_tuple_struct_elt(self.tag).map(Some),
+            2 => serializer.serialize_tuple_struct_elt(self.value).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> Option<usize> {
+        Some(2)
+    }
+}
+
+/// Describes how a value was encoded, see `de::from_slice_annotated`.
+///
+/// Annotations form a tree in parallel to the decoded `Value`. The children follow the order of
+/// the input: the elements of an array, the keys and values of a map in turns, the value inside
+/// of a tag and the chunks of a string of indefinite length.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    /// The number of bytes following the initial byte that hold the argument, that is the
+    /// integer, length, tag or float. It is 0 if the argument is part of the initial byte.
+    pub width: u8,
+    /// Whether the string or container has an indefinite length.
+    pub indefinite: bool,
+    /// The annotations of the nested values.
+    pub children: Vec<Annotation>,
+}
+
+/// The name under which a `RawValue` passes through the (de)serializer.
+#[doc(hidden)]
+pub const RAW_VALUE_NAME: &str = "__cbor_raw_value";
+
+/// The name under which a tagged `Value` passes through the (de)serializer.
+///
+/// The tag number and the value travel as a pair, which is also how other data formats see them.
+#[doc(hidden)]
+pub const TAG_NAME: &str = "__cbor_tag";
+
+/// The encoding of a CBOR value, kept as is.
+///
+/// Deserializing a `RawValue` copies the bytes of the next value without decoding it, serializing
+/// it writes these bytes back unchanged. This allows forwarding parts of a document untouched.
+///
+/// The bytes are always copied, also out of a slice. serde 0.7 has no borrowed deserialization,
+/// `Deserialize` carries no lifetime of the input, so a `RawValue<'de>` borrowing them is not
+/// possible.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawValue(Vec<u8>);
+
+impl RawValue {
+    /// Wraps the encoding of a CBOR value. The bytes are not checked.
+    #[inline]
+    pub fn from_bytes(bytes: Vec<u8>) -> RawValue {
+        RawValue(bytes)
+    }
+
+    /// Returns the encoded value.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the encoded value.
+    #[inline]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl de::Deserialize for RawValue {
+    #[inline]
+    fn deserialize<D>(deserializer: &mut D) -> Result<RawValue, D::Error>
+        where D: de::Deserializer,
+    {
+        struct RawValueVisitor;
+
+        impl de::Visitor for RawValueVisitor {
+            type Value = RawValue;
+
+            #[inline]
+            fn visit_byte_buf<E>(&mut self, v: Vec<u8>) -> Result<Self::Value, E>
+                where E: de::Error
+            {
+                Ok(RawValue(v))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(RAW_VALUE_NAME, RawValueVisitor)
+    }
+}
+
+impl ser::Serialize for RawValue {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(RAW_VALUE_NAME, Bytes::from(&self.0[..]))
+    }
+}
+
+/// A CBOR number, either an integer or a floating point value.
+///
+/// Integers are kept apart from floats and unsigned from negative integers, so decoding and
+/// encoding a `Number` preserves the kind of number it was.
+///
+/// The width of a float is not kept. Half and single precision floats both reach `Deserialize`
+/// as an `f32`, and a float is encoded again in the smallest width holding it exactly, down to
+/// single precision or with `Serializer::prefer_smallest_float` to half precision. The half
+/// precision `0xf9 0x3e 0x00` becomes `0xfa 0x3f 0xc0 0x00 0x00`, a double holding 1.5 too.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Number(N);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum N {
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl Number {
+    /// Returns true if the number is an integer that fits into an i64.
+    #[inline]
+    pub fn is_i64(&self) -> bool {
+        self.as_i64().is_some()
+    }
+
+    /// Returns true if the number is a non-negative integer.
+    #[inline]
+    pub fn is_u64(&self) -> bool {
+        self.as_u64().is_some()
+    }
+
+    /// Returns true if the number is a floating point value.
+    #[inline]
+    pub fn is_f64(&self) -> bool {
+        match self.0 {
+            N::F64(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the number if it is an integer that fits into an i64. Returns None otherwise.
+    #[inline]
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.0 {
+            N::U64(n) if n <= ::std::i64::MAX as u64 => Some(n as i64),
+            N::I64(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if it is a non-negative integer. Returns None otherwise.
+    #[inline]
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.0 {
+            N::U64(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number cast to a f64.
+    #[inline]
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.0 {
+            N::U64(n) => Some(n as f64),
+            N::I64(n) => Some(n as f64),
+            N::F64(n) => Some(n),
+        }
+    }
+}
+
+impl From<u64> for Number {
+    #[inline]
+    fn from(n: u64) -> Number {
+        Number(N::U64(n))
+    }
+}
+
+impl From<i64> for Number {
+    #[inline]
+    fn from(n: i64) -> Number {
+        if n < 0 {
+            Number(N::I64(n))
+        } else {
+            Number(N::U64(n as u64))
+        }
+    }
+}
+
+impl From<f64> for Number {
+    #[inline]
+    fn from(n: f64) -> Number {
+        Number(N::F64(n))
+    }
+}
+
+impl de::Deserialize for Number {
+    #[inline]
+    fn deserialize<D>(deserializer: &mut D) -> Result<Number, D::Error>
+        where D: de::Deserializer,
+    {
+        struct NumberVisitor;
+
+        impl de::Visitor for NumberVisitor {
+            type Value = Number;
+
+            #[inline]
+            fn visit_u64<E>(&mut self, v: u64) -> Result<Self::Value, E>
+                where E: de::Error
+            {
+                Ok(Number::from(v))
+            }
+
+            #[inline]
+            fn visit_i64<E>(&mut self, v: i64) -> Result<Self::Value, E>
+                where E: de::Error
+            {
+                Ok(Number::from(v))
+            }
+
+            #[inline]
+            fn visit_f64<E>(&mut self, v: f64) -> Result<Self::Value, E>
+                where E: de::Error
+            {
+                Ok(Number::from(v))
+            }
+        }
+
+        deserializer.deserialize(NumberVisitor)
+    }
+}
+
+impl ser::Serialize for Number {
+    #[inline]
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: ser::Serializer,
+    {
+        match self.0 {
+            N::U64(n) => serializer.serialize_u64(n),
+            N::I64(n) => serializer.serialize_i64(n),
+            N::F64(n) => serializer.serialize_f64(n),
         }
     }
 }
@@ -1,8 +1,52 @@
+extern crate serde;
+#[macro_use]
 extern crate serde_cbor;
 
-use std::collections::HashMap;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
+use std::io::{self, Read};
+use std::ops::ControlFlow;
+use std::rc::Rc;
 
-use serde_cbor::{Value, ObjectKey, error, de};
+use serde::de::{Deserialize, Deserializer, EnumVisitor, MapVisitor, VariantVisitor, Visitor};
+use serde::bytes::ByteBuf;
+use serde::de::impls::{IgnoredAny, TupleVisitor2};
+use serde_cbor::{Value, ObjectKey, RawValue, Annotation, Number, error, de};
+use serde_cbor::ser::Serializer;
+
+/// Decodes a whole document with a configured deserializer.
+fn decode<T: Deserialize>(mut de: de::Deserializer<&[u8]>) -> error::Result<T> {
+    let value = try!(T::deserialize(&mut de));
+    try!(de.end());
+    Ok(value)
+}
+
+/// Keeps what a callback of the deserializer sees.
+struct Recorder<T>(Rc<Cell<Vec<T>>>);
+
+impl<T> Recorder<T> {
+    fn new() -> Recorder<T> {
+        Recorder(Rc::new(Cell::new(Vec::new())))
+    }
+
+    fn push(&self, item: T) {
+        let mut items = self.0.take();
+        items.push(item);
+        self.0.set(items);
+    }
+
+    fn take(&self) -> Vec<T> {
+        self.0.take()
+    }
+}
+
+impl<T> Clone for Recorder<T> {
+    fn clone(&self) -> Recorder<T> {
+        Recorder(self.0.clone())
+    }
+}
 
 #[test]
 fn test_string1() {
@@ -24,12 +68,191 @@ fn test_string3() {
     assert_eq!(value.unwrap(), Value::String("I met a traveller from an antique land who said".to_owned()));
 }
 
+#[test]
+fn test_invalid_utf8() {
+    fn offset(input: &[u8]) -> usize {
+        let mut error = de::from_slice::<Value>(input).unwrap_err();
+        while let error::Error::AtIndex(_, inner) = error {
+            error = *inner;
+        }
+        match error {
+            error::Error::InvalidUtf8(offset) => offset,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    // overlong encoding of '/'
+    assert_eq!(offset(b"\x62\xc0\xaf"), 1);
+    // surrogate U+D800
+    assert_eq!(offset(b"\x65ab\xed\xa0\x80"), 3);
+    assert_eq!(offset(b"\x82\x61a\x63x\xff\x00"), 5);
+    assert_eq!(offset(b"\x7f\x62ok\x62a\xc0\xff"), 6);
+    assert_eq!(offset(b"\xa1\x78\x02\xe0\x80\x00"), 3);
+    assert_eq!(error::Error::InvalidUtf8(4).to_string(), "invalid UTF-8 at offset 4");
+}
+
+#[test]
+fn test_collect_errors() {
+    let (value, errors) =
+        de::collect_errors::<HashMap<String, u32>>(b"\xa3\x61a\x01\x61b\x61x\x61c\x03");
+    let mut expected = HashMap::new();
+    expected.insert("a".to_owned(), 1);
+    expected.insert("c".to_owned(), 3);
+    assert_eq!(value, Some(expected));
+    assert_eq!(errors.len(), 1);
+
+    let (value, errors) = de::collect_errors::<BTreeMap<String, BTreeMap<String, u32>>>(
+        b"\xa3\x61a\xa2\x61\xff\x01\x61y\x01\x61b\xa1\x61z\x02\x61c\x03");
+    let value = value.unwrap();
+    assert_eq!(value.keys().collect::<Vec<_>>(), ["a", "b"]);
+    assert_eq!(value["a"].get("y"), Some(&1));
+    assert_eq!(value["a"].len(), 1);
+    assert_eq!(value["b"].get("z"), Some(&2));
+    assert_eq!(errors.len(), 2);
+    match errors[0] {
+        error::Error::InvalidUtf8(5) => {}
+        ref other => panic!("{:?}", other),
+    }
+
+    let (value, errors) = de::collect_errors::<Value>(b"\xbf\x61a\x01\x61b\x61\xff\xff");
+    assert!(value.unwrap().as_object().unwrap().len() == 1);
+    assert_eq!(errors.len(), 1);
+
+    let (value, errors) = de::collect_errors::<HashMap<String, u32>>(b"\xa2\x61a\x01\x61");
+    assert_eq!(value, None);
+    assert_eq!(errors.len(), 1);
+    let (value, errors) = de::collect_errors::<HashMap<String, u32>>(b"\xa1\x61a\x01\x00");
+    assert!(value.is_some());
+    match errors[..] {
+        [error::Error::TrailingBytes { .. }] => {}
+        ref other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_collect_errors_limits() {
+    let mut deep = b"\xa1\x61a".to_vec();
+    deep.extend(vec![0x81; 1000000]);
+    deep.push(0x00);
+    let mut de = de::Deserializer::new(&deep[..]).max_depth(Some(16)).collect_errors(true);
+    match HashMap::<String, Value>::deserialize(&mut de) {
+        Err(error::Error::DepthLimitExceeded) => {}
+        other => panic!("{:?}", other),
+    }
+
+    // {"a": [1, 2, 3], "b": [4, 5]} has 7 items.
+    let slice = b"\xa2\x61a\x83\x01\x02\x03\x61b\x82\x04\x05";
+    for &(max, ok) in &[(6, false), (7, true)] {
+        let mut de = de::Deserializer::new(&slice[..])
+            .max_total_items(Some(max))
+            .collect_errors(true);
+        assert_eq!(HashMap::<String, Vec<u8>>::deserialize(&mut de).is_ok(), ok);
+    }
+
+    fn headers(slice: &[u8], collect: bool) -> Vec<de::Header> {
+        let headers = Recorder::new();
+        let seen = headers.clone();
+        let de = de::Deserializer::new(slice)
+            .collect_errors(collect)
+            .trace(move |header| seen.push(*header));
+        decode::<HashMap<String, Vec<u8>>>(de).unwrap();
+        headers.take()
+    }
+    assert_eq!(headers(slice, true), headers(slice, false));
+    assert_eq!(headers(slice, true).len(), 10);
+
+    let mut de = de::Deserializer::new(&b"\xa1\x61a\xd9\x02\x2b\x61b"[..])
+        .collect_errors(true)
+        .register_tag_handler(555, |_, de| String::deserialize(de).map(Value::String));
+    let value = HashMap::<String, String>::deserialize(&mut de).unwrap();
+    assert_eq!(value["a"], "b");
+}
+
+#[test]
+fn test_error_at_index() {
+    let value: error::Result<(u32, String, ByteBuf)> = de::from_slice(b"\x83\x01\x61a\x01");
+    match value {
+        Err(error::Error::AtIndex(2, _)) => {}
+        other => panic!("{:?}", other),
+    }
+    let value: error::Result<Vec<(u8, bool)>> = de::from_slice(b"\x82\x82\x01\xf4\x82\x02\x02");
+    let error = value.unwrap_err();
+    match error {
+        error::Error::AtIndex(1, ref inner) => match **inner {
+            error::Error::AtIndex(1, _) => {}
+            ref other => panic!("{:?}", other),
+        },
+        ref other => panic!("{:?}", other),
+    }
+    assert!(error.to_string().starts_with("at index 1: at index 1: "));
+    let value: error::Result<Vec<u8>> = de::from_slice(b"\x9f\x01\x02");
+    match value {
+        Err(error::Error::AtIndex(2, ref inner)) => match **inner {
+            error::Error::Io(_) | error::Error::Eof => {}
+            ref other => panic!("{:?}", other),
+        },
+        other => panic!("{:?}", other),
+    }
+}
+
 #[test]
 fn test_byte_string() {
     let value: error::Result<Value> = de::from_slice(&[0x46, 0x66, 0x6f, 0x6f, 0x62, 0x61, 0x72]);
     assert_eq!(value.unwrap(), Value::Bytes(b"foobar".to_vec()));
 }
 
+#[test]
+fn test_bytes_into_seq() {
+    let value: Vec<u8> = de::from_slice(b"\x43\x01\x02\xff").unwrap();
+    assert_eq!(value, vec![1, 2, 255]);
+    let value: Cow<[u8]> = de::from_slice(b"\x5f\x41\x01\x42\x02\x03\xff").unwrap();
+    assert_eq!(&value[..], &[1, 2, 3]);
+    let value: Cow<[u8]> = de::from_reader(&b"\x40"[..]).unwrap();
+    assert!(value.is_empty());
+    let value: Vec<u8> = de::from_slice(b"\x82\x01\x02").unwrap();
+    assert_eq!(value, vec![1, 2]);
+    let value: (u8, Vec<u8>) = de::from_slice(b"\x82\x07\x42ab").unwrap();
+    assert_eq!(value, (7, b"ab".to_vec()));
+    assert!(de::from_slice::<Vec<bool>>(b"\x41\x01").is_err());
+
+    // Other sequences of bytes accept byte strings too, but only bytes can be read from them.
+    let value: (u8, u8) = de::from_slice(b"\x42\x01\x02").unwrap();
+    assert_eq!(value, (1, 2));
+    let value: VecDeque<u8> = de::from_slice(b"\x42\x01\x02").unwrap();
+    assert_eq!(value, vec![1, 2]);
+    for result in vec![de::from_slice::<Vec<u16>>(b"\x42\x01\x02").map(|_| ()),
+                       de::from_slice::<(u8, i8)>(b"\x42\x01\x02").map(|_| ()),
+                       de::from_slice::<Vec<Value>>(b"\x41\x01").map(|_| ())] {
+        match result {
+            Err(error::Error::Custom(ref message)) => {
+                assert_eq!(message, "Invalid value: the elements of a byte string are `u8`")
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+    assert!(de::from_slice::<Vec<u8>>(b"\x43\x01\x02").is_err());
+    assert_eq!(de::from_slice::<ByteBuf>(b"\x42\x01\x02").unwrap().len(), 2);
+    assert!(de::from_slice::<ByteBuf>(b"\xf6").unwrap().is_empty());
+}
+
+#[test]
+fn test_empty_strings() {
+    let value: Option<ByteBuf> = de::from_slice(b"\x40").unwrap();
+    assert_eq!(value, Some(ByteBuf::new()));
+    let value: Option<ByteBuf> = de::from_slice(b"\x5f\xff").unwrap();
+    assert_eq!(value, Some(ByteBuf::new()));
+    let value: Option<ByteBuf> = de::from_slice(b"\xf6").unwrap();
+    assert_eq!(value, None);
+    let value: Option<String> = de::from_slice(b"\x60").unwrap();
+    assert_eq!(value, Some(String::new()));
+    let value: Option<String> = de::from_slice(b"\x7f\xff").unwrap();
+    assert_eq!(value, Some(String::new()));
+    let value: Option<String> = de::from_slice(b"\xf6").unwrap();
+    assert_eq!(value, None);
+    let value: Value = de::from_slice(b"\x82\x40\x60").unwrap();
+    assert_eq!(value, Value::Array(vec![Value::Bytes(vec![]), Value::String(String::new())]));
+}
+
 #[test]
 fn test_numbers1() {
     let value: error::Result<Value> = de::from_slice(&[0x00]);
@@ -48,6 +271,39 @@ fn test_numbers3() {
     assert_eq!(value.unwrap(), Value::I64(-2015));
 }
 
+#[test]
+fn test_number() {
+    let number: Number = de::from_slice(&[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).unwrap();
+    assert_eq!(number.as_u64(), Some(::std::u64::MAX));
+    assert_eq!(number.as_i64(), None);
+    let number: Number = de::from_slice(&[0x39, 0x07, 0xde]).unwrap();
+    assert_eq!((number.as_u64(), number.as_i64()), (None, Some(-2015)));
+    let number: Number = de::from_slice(&[0x18, 0x2a]).unwrap();
+    assert_eq!((number.as_u64(), number.as_i64(), number.is_f64()), (Some(42), Some(42), false));
+    let number: Number = de::from_slice(&[0xf9, 0x3e, 0x00]).unwrap();
+    assert_eq!((number.as_i64(), number.as_f64(), number.is_f64()), (None, Some(1.5), true));
+    let number: Number = de::from_slice(&[0xf9, 0x3c, 0x00]).unwrap();
+    assert_eq!((number.as_u64(), number.as_f64()), (None, Some(1.0)));
+    assert!(de::from_slice::<Number>(b"\x61a").is_err());
+    assert!(de::from_slice::<Number>(&[0xf5]).is_err());
+
+    for bytes in &[&b"\x18\x2a"[..], b"\x39\x07\xde", b"\x1b\xff\xff\xff\xff\xff\xff\xff\xff",
+                   b"\xfa\x3f\x80\x00\x00", b"\xfb\x3f\xf1\x99\x99\x99\x99\x99\x9a"] {
+        let number: Number = de::from_slice(bytes).unwrap();
+        assert_eq!(&serde_cbor::to_vec(&number).unwrap()[..], *bytes);
+    }
+
+    // The width of floats is lost, they are written in the smallest width holding them.
+    for bytes in &[&b"\xf9\x3e\x00"[..], b"\xfb\x3f\xf8\x00\x00\x00\x00\x00\x00"] {
+        let number: Number = de::from_slice(bytes).unwrap();
+        assert_eq!(serde_cbor::to_vec(&number).unwrap(), b"\xfa\x3f\xc0\x00\x00");
+        let mut vec = Vec::new();
+        let mut serializer = Serializer::new(&mut vec).prefer_smallest_float();
+        serde::Serialize::serialize(&number, &mut serializer).unwrap();
+        assert_eq!(vec, b"\xf9\x3e\x00");
+    }
+}
+
 #[test]
 fn test_bool() {
     let value: error::Result<Value> = de::from_slice(b"\xf4");
@@ -58,6 +314,476 @@ fn test_bool() {
 fn test_trailing_bytes() {
     let value: error::Result<Value> = de::from_slice(b"\xf4trailing");
     assert!(value.is_err());
+    match de::from_slice::<u8>(b"\x01\x82\x01\x02") {
+        Err(error::Error::TrailingBytes { next_major: 4, remaining: Some(3) }) => {}
+        other => panic!("{:?}", other),
+    }
+    match de::from_slice::<u8>(b"\x01\x00\x00\xf7") {
+        Err(ref e @ error::Error::TrailingBytes { next_major: 0, remaining: Some(3) }) => {
+            assert_eq!(e.to_string(), "unexpected trailing bytes: 3 bytes starting with major type 0");
+        }
+        other => panic!("{:?}", other),
+    }
+    // The length of other readers is not known, the rest of them is left unread.
+    let mut reader = io::repeat(0x01).take(50_000_000);
+    match de::from_reader::<u8, _>(&mut reader) {
+        Err(error::Error::TrailingBytes { next_major: 0, remaining: None }) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(reader.limit(), 50_000_000 - 2);
+    let mut de = de::Deserializer::from_slice(b"\x01\x00\x00\xf7").allow_padding(Some(0));
+    assert_eq!(u8::deserialize(&mut de).unwrap(), 1);
+    match de.end() {
+        Err(error::Error::TrailingBytes { next_major: 7, remaining: Some(1) }) => {}
+        other => panic!("{:?}", other),
+    }
+    match de::from_slice::<(u8,)>(b"\x82\x01\x02") {
+        Err(error::Error::TrailingItems) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_padding() {
+    let mut de = de::Deserializer::new(&b"\x01\x00\x00\x00"[..]).allow_padding(Some(0));
+    let value: u8 = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, 1);
+    assert!(de.end().is_ok());
+
+    let mut de = de::Deserializer::new(&b"\x01\x00\x01"[..]).allow_padding(Some(0));
+    let _: u8 = Deserialize::deserialize(&mut de).unwrap();
+    assert!(de.end().is_err());
+
+    let value: error::Result<u8> = de::from_slice(b"\x01\x00");
+    assert!(value.is_err());
+
+    let de = de::Deserializer::new(&b"\x01\x00\x00\x02\x00"[..]).allow_padding(Some(0));
+    let values = de::StreamDeserializer::new(de).collect::<error::Result<Vec<u8>>>().unwrap();
+    assert_eq!(values, vec![1, 2]);
+}
+
+#[test]
+fn test_with_bytes() {
+    let slice = b"\x82\x01\x63abc";
+    let (value, bytes): (Value, &[u8]) = de::from_slice_with_bytes(slice).unwrap();
+    assert_eq!(value, Value::Array(vec![Value::U64(1), Value::String("abc".to_owned())]));
+    assert_eq!(bytes, &slice[..]);
+    assert!(de::from_slice_with_bytes::<Value>(b"\x01\x02").is_err());
+}
+
+#[test]
+fn test_peek_container_len() {
+    let slice = b"\x98\x20\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10\
+                  \x11\x12\x13\x14\x15\x16\x17\x18\x18\x18\x19\x18\x1a\x18\x1b\x18\x1c\x18\x1d\
+                  \x18\x1e\x18\x1f\x18\x20";
+    let mut de = de::Deserializer::new(&slice[..]);
+    assert_eq!(de.peek_container_len().unwrap(), Some(32));
+    assert_eq!(de.peek_container_len().unwrap(), Some(32));
+    let value: Vec<u8> = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, (1..33).collect::<Vec<_>>());
+    assert!(de.end().is_ok());
+
+    let mut de = de::Deserializer::new(&b"\xa1\x01\x02"[..]);
+    assert_eq!(de.peek_container_len().unwrap(), Some(1));
+    let value: HashMap<u8, u8> = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value[&1], 2);
+
+    let mut de = de::Deserializer::new(&b"\x9f\x01\xff"[..]);
+    assert_eq!(de.peek_container_len().unwrap(), None);
+    let value: Option<Vec<u8>> = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, Some(vec![1]));
+
+    let mut de = de::Deserializer::new(&b"\x63abc"[..]);
+    assert!(de.peek_container_len().is_err());
+
+    // After a failed read the length can be peeked again.
+    struct Stalling(Vec<&'static [u8]>);
+
+    impl Read for Stalling {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop() {
+                Some(b"") => Err(io::ErrorKind::WouldBlock.into()),
+                Some(bytes) => {
+                    buf[..bytes.len()].copy_from_slice(bytes);
+                    Ok(bytes.len())
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    let reader = Stalling(vec![b"\x02", b"\x01", b"\x02", b"\x00", b"", b"\x99"]);
+    let mut de = de::Deserializer::new(reader);
+    assert!(de.peek_container_len().is_err());
+    assert_eq!(de.peek_container_len().unwrap(), Some(2));
+    let value: Vec<u8> = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, vec![1, 2]);
+}
+
+#[test]
+fn test_lenient_numbers() {
+    let mut de = de::Deserializer::new(&b"\x84\x6212\x64-300\x632.5\x18\x2a"[..]).lenient_numbers(true);
+    let value: (u32, i16, f64, u8) = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, (12, -300, 2.5, 42));
+    let mut de = de::Deserializer::new(&b"\x63300"[..]).lenient_numbers(true);
+    assert!(u8::deserialize(&mut de).is_err());
+    let mut de = de::Deserializer::new(&b"\x63abc"[..]).lenient_numbers(true);
+    assert!(u64::deserialize(&mut de).is_err());
+    let mut de = de::Deserializer::new(&b"\x6312a"[..]).lenient_numbers(true);
+    assert_eq!(String::deserialize(&mut de).unwrap(), "12a");
+    assert!(de::from_slice::<u32>(b"\x6212").is_err());
+}
+
+#[test]
+fn test_checked() {
+    let (value, canonical) = de::from_slice_checked::<Value>(b"\xa2\x61a\x18\x18\x62aa\x82\x01\x39\x01\x00").unwrap();
+    assert_eq!(value, de::from_slice::<Value>(b"\xa2\x61a\x18\x18\x62aa\x82\x01\x39\x01\x00").unwrap());
+    assert!(canonical);
+    let inputs: &[&[u8]] = &[
+        b"\x18\x17",
+        b"\x39\x00\xff",
+        b"\x1b\x00\x00\x00\x00\xff\xff\xff\xff",
+        b"\x78\x01a",
+        b"\x9f\x01\xff",
+        b"\x7f\x61a\xff",
+        b"\xa2\x62aa\x01\x61b\x02",
+        b"\xa2\x61a\x01\x61a\x02",
+        b"\x81\xa2\x02\x01\x01\x02",
+    ];
+    for input in inputs {
+        let (_, canonical) = de::from_slice_checked::<Value>(input).unwrap();
+        assert!(!canonical, "{:?}", input);
+    }
+    let (value, canonical) = de::from_slice_checked::<Vec<u8>>(b"\x82\x18\x18\x18\xff").unwrap();
+    assert_eq!(value, vec![24, 255]);
+    assert!(canonical);
+    assert!(de::from_slice_checked::<Value>(b"\x18\x17\x00").is_err());
+}
+
+#[test]
+fn test_signed_zero_keys() {
+    let mut vec = Vec::new();
+    {
+        let mut serializer = Serializer::new(&mut vec).canonical().prefer_smallest_float();
+        serde_cbor::ser::serialize_map_iter(&mut serializer, vec![(-0.0f64, 2u8), (0.0, 1)], None).unwrap();
+    }
+    assert_eq!(vec, b"\xa2\xf9\x00\x00\x01\xf9\x80\x00\x02");
+    let (_, canonical) = de::from_slice_checked::<IgnoredAny>(&vec).unwrap();
+    assert!(canonical);
+    let entries = de::decode_map_entries(&vec).unwrap();
+    let keys = entries.iter().map(|entry| entry.0.as_f64().unwrap().to_bits()).collect::<Vec<_>>();
+    assert_eq!(keys, vec![0.0f64.to_bits(), (-0.0f64).to_bits()]);
+    assert_eq!((&entries[0].1, &entries[1].1), (&Value::U64(1), &Value::U64(2)));
+    let (_, canonical) = de::from_slice_checked::<IgnoredAny>(b"\xa2\xf9\x80\x00\x02\xf9\x00\x00\x01").unwrap();
+    assert!(!canonical);
+    let (_, canonical) = de::from_slice_checked::<IgnoredAny>(b"\xa2\xf9\x00\x00\x01\xf9\x00\x00\x02").unwrap();
+    assert!(!canonical);
+}
+
+#[test]
+fn test_strict_canonical() {
+    let strict = |input: &'static [u8]| de::Deserializer::new(input).strict_canonical(true);
+    let canonical = b"\xa2\x01\x18\x64\x02\x82\x20\x61a";
+    let value: Value = decode(strict(canonical)).unwrap();
+    assert_eq!(value, de::from_slice::<Value>(canonical).unwrap());
+    assert_eq!(decode::<u8>(strict(b"\x18\x18")).unwrap(), 24);
+
+    // Not the shortest form, unsorted and duplicate keys and indefinite lengths.
+    for input in &[&b"\x18\x05"[..], b"\x39\x00\x05", b"\x81\x19\x00\x05",
+                   b"\xa2\x02\x00\x01\x00", b"\xa2\x01\x00\x01\x00",
+                   b"\x9f\x01\xff", b"\xbf\x01\x00\xff", b"\x5f\x41\x00\xff"] {
+        match decode::<Value>(strict(input)) {
+            Err(error::Error::NonCanonical) => {}
+            Err(error::Error::AtIndex(0, ref error))
+                if matches!(**error, error::Error::NonCanonical) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(de::from_slice::<Value>(input).is_ok());
+    }
+    match decode::<HashMap<u8, u8>>(strict(b"\xa2\x02\x00\x01\x00")) {
+        Err(error::Error::NonCanonical) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_strict_canonical_nan() {
+    let strict = |input: &'static [u8]| de::Deserializer::new(input).strict_canonical(true);
+    assert!(decode::<f64>(strict(b"\xf9\x7e\x00")).unwrap().is_nan());
+    assert_eq!(decode::<f64>(strict(b"\xf9\x3c\x00")).unwrap(), 1.0);
+    assert_eq!(decode::<f64>(strict(b"\xfb\x3f\xf1\x99\x99\x99\x99\x99\x9a")).unwrap(), 1.1);
+    for input in &[&b"\xf9\x7e\x01"[..], b"\xf9\xfe\x00", b"\xf9\x7d\x00", b"\xfa\x7f\xc0\x00\x00",
+                   b"\xfb\x7f\xf8\x00\x00\x00\x00\x00\x00", b"\xfb\x7f\xf0\x00\x00\x00\x00\x00\x01"] {
+        match decode::<f64>(strict(input)) {
+            Err(error::Error::NonCanonicalNaN) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(de::from_slice::<f64>(input).unwrap().is_nan());
+        assert!(!de::from_slice_checked::<f64>(input).unwrap().1);
+    }
+    assert!(de::from_slice_checked::<f64>(b"\xf9\x7e\x00").unwrap().1);
+}
+
+#[test]
+fn test_decode_map_entries() {
+    let entries = de::decode_map_entries(b"\xa3\x61b\x01\x01\xf5\x61b\x82\x01\x02").unwrap();
+    assert_eq!(entries, vec![
+        (Value::String("b".to_owned()), Value::U64(1)),
+        (Value::U64(1), Value::Bool(true)),
+        (Value::String("b".to_owned()), Value::Array(vec![Value::U64(1), Value::U64(2)])),
+    ]);
+    let entries = de::decode_map_entries(b"\xbf\x61a\xf6\x20\x9f\xff\xff").unwrap();
+    assert_eq!(entries, vec![
+        (Value::String("a".to_owned()), Value::Null),
+        (Value::I64(-1), Value::Array(vec![])),
+    ]);
+    assert_eq!(de::decode_map_entries(b"\xa0").unwrap(), vec![]);
+    assert!(de::decode_map_entries(b"\x82\x01\x02").is_err());
+    assert!(de::decode_map_entries(b"\xa1\x01").is_err());
+    assert!(de::decode_map_entries(b"\xa0\x00").is_err());
+}
+
+int_labeled! {
+    #[derive(Debug, PartialEq)]
+    struct CoseHeader {
+        pub alg: i64 = 1,
+        kid: Option<ByteBuf> = 4,
+        pub(crate) content_type: Option<u64> = 3,
+        counter: Option<i64> = -1,
+    }
+}
+
+#[test]
+fn test_int_labeled() {
+    let header: CoseHeader = de::from_slice(b"\xa4\x01\x26\x04\x42ab\x20\x18\x64\x61x\xf5").unwrap();
+    assert_eq!(header, CoseHeader {
+        alg: -7,
+        kid: Some(ByteBuf::from(b"ab".to_vec())),
+        content_type: None,
+        counter: Some(100),
+    });
+    let encoded = serde_cbor::to_vec(&header).unwrap();
+    assert_eq!(encoded, b"\xa3\x01\x26\x04\x82\x18\x61\x18\x62\x20\x18\x64");
+    assert_eq!(de::from_slice::<CoseHeader>(&encoded).unwrap(), header);
+    let header: CoseHeader = de::from_slice(b"\xbf\x18\x63\x00\x01\x01\xff").unwrap();
+    assert_eq!((header.alg, header.kid), (1, None));
+    let header = CoseHeader { alg: 1, kid: None, content_type: None, counter: None };
+    assert_eq!(serde_cbor::to_vec(&header).unwrap(), b"\xa1\x01\x01");
+    assert!(de::from_slice::<CoseHeader>(b"\xa1\x04\x41a").is_err());
+    assert!(de::from_slice::<CoseHeader>(b"\xa2\x01\x01\x01\x02").is_err());
+    assert!(de::from_slice::<CoseHeader>(b"\x81\x01").is_err());
+}
+
+#[test]
+fn test_extract_field() {
+    let slice = b"\xa4\x01\x02\x61a\x82\x01\x02\x61b\x63foo\x61c\x01";
+    assert_eq!(de::extract_field::<String>(slice, "b").unwrap(), Some("foo".to_owned()));
+    assert_eq!(de::extract_field::<Vec<u8>>(slice, "a").unwrap(), Some(vec![1, 2]));
+    assert_eq!(de::extract_field::<u8>(slice, "d").unwrap(), None);
+    // Decoding stops at the matching entry.
+    let slice = b"\xbf\x61a\x9f\xff\x61b\xf5\x61c";
+    assert_eq!(de::extract_field::<bool>(slice, "b").unwrap(), Some(true));
+    assert_eq!(de::extract_field::<u8>(b"\xbf\x61a\x01\xff", "b").unwrap(), None);
+    assert!(de::extract_field::<u8>(slice, "d").is_err());
+    assert!(de::extract_field::<u8>(b"\x82\x61b\x01", "b").is_err());
+}
+
+#[test]
+fn test_lenient_bools() {
+    let mut de = de::Deserializer::new(&b"\x83\x00\x01\xf5"[..]).lenient_bools(true);
+    let value: Vec<bool> = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, vec![false, true, true]);
+    let mut de = de::Deserializer::new(&b"\x02"[..]).lenient_bools(true);
+    assert!(bool::deserialize(&mut de).is_err());
+    let mut de = de::Deserializer::new(&b"\x18\x01"[..]).lenient_bools(true);
+    assert_eq!(bool::deserialize(&mut de).unwrap(), true);
+    let mut de = de::Deserializer::new(&b"\x20"[..]).lenient_bools(true);
+    assert!(bool::deserialize(&mut de).is_err());
+    let mut de = de::Deserializer::new(&b"\x01"[..]).lenient_bools(true);
+    assert_eq!(u8::deserialize(&mut de).unwrap(), 1);
+    assert!(de::from_slice::<bool>(b"\x01").is_err());
+}
+
+#[test]
+fn test_decode_by_tag() {
+    fn decode_u32(de: &mut de::Deserializer<&[u8]>) -> error::Result<Box<Any>> {
+        Ok(Box::new(try!(u32::deserialize(de))))
+    }
+
+    fn decode_person(de: &mut de::Deserializer<&[u8]>) -> error::Result<Box<Any>> {
+        Ok(Box::new(try!(Person::deserialize(de))))
+    }
+
+    let mut registry = HashMap::new();
+    registry.insert(40000, decode_u32 as de::TagDecoder);
+    registry.insert(40001, decode_person as de::TagDecoder);
+    let value = de::decode_by_tag(b"\xd9\x9c\x40\x18\x2a", &registry).unwrap();
+    assert_eq!(value.downcast_ref::<u32>(), Some(&42));
+    let value = de::decode_by_tag(b"\xd9\x9c\x41\xa2\x69firstName\x63Ada\x63AGE\x18\x24", &registry).unwrap();
+    assert_eq!(value.downcast_ref::<Person>(), Some(&Person { first_name: "Ada".to_owned(), age: 36 }));
+    match de::decode_by_tag(b"\xd9\x9c\x42\x18\x2a", &registry) {
+        Err(error::Error::UnknownTag(40002)) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    assert!(de::decode_by_tag(b"\x18\x2a", &registry).is_err());
+    assert!(de::decode_by_tag(b"\xd9\x9c\x40\x18\x2a\x00", &registry).is_err());
+}
+
+#[test]
+fn test_tag_handlers() {
+    fn upper(tag: u64, de: &mut de::Deserializer<&[u8]>) -> error::Result<Value> {
+        assert_eq!(tag, 555);
+        let s = try!(String::deserialize(de));
+        if s.is_empty() {
+            return Err(serde::de::Error::invalid_value("empty string"));
+        }
+        Ok(Value::String(s.to_uppercase()))
+    }
+
+    let handlers = |input: &'static [u8]| {
+        de::Deserializer::new(input)
+            .register_tag_handler(555, upper)
+            .register_tag_handler(2, |_, de| {
+                ByteBuf::deserialize(de).map(|bytes| Value::U64(bytes.len() as u64))
+            })
+            .register_tag_handler(!0, |_, de| u64::deserialize(de).map(|n| Value::U64(n + 1)))
+    };
+
+    let value: (String, String, u64) =
+        decode(handlers(b"\x83\xd9\x02\x2b\x62ab\xd9\x02\x2c\x62cd\xc2\x42\x01\x02")).unwrap();
+    assert_eq!(value, ("AB".to_owned(), "cd".to_owned(), 2));
+    let value: Value = decode(handlers(b"\x82\xd9\x02\x2b\x62ab\xd9\x02\x2c\x62cd")).unwrap();
+    assert_eq!(value, Value::Array(vec![Value::String("AB".to_owned()),
+                                        Value::Tag(556, Box::new(Value::String("cd".to_owned())))]));
+    let value: Value = decode(handlers(b"\xc3\x41\x00")).unwrap();
+    assert_eq!(value, Value::Tag(3, Box::new(Value::Bytes(vec![0]))));
+    assert!(decode::<String>(handlers(b"\xd9\x02\x2b\x60")).is_err());
+    assert!(decode::<u64>(handlers(b"\xd9\x02\x2b\x62ab")).is_err());
+    let value: u64 = decode(handlers(b"\xdb\xff\xff\xff\xff\xff\xff\xff\xff\x01")).unwrap();
+    assert_eq!(value, 2);
+    // The additional information 31 is not the largest tag.
+    match decode::<Value>(handlers(b"\xdf\x01")) {
+        Err(error::Error::Syntax) => {}
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_typed_frames() {
+    fn decode_u32(de: &mut de::Deserializer<&[u8]>) -> error::Result<Box<Any>> {
+        Ok(Box::new(try!(u32::deserialize(de))))
+    }
+
+    fn decode_string(de: &mut de::Deserializer<&[u8]>) -> error::Result<Box<Any>> {
+        Ok(Box::new(try!(String::deserialize(de))))
+    }
+
+    fn dispatch(kind: u8) -> Option<de::FrameDecoder> {
+        match kind {
+            1 => Some(decode_u32),
+            2 => Some(decode_string),
+            _ => None,
+        }
+    }
+
+    let mut stream = Vec::new();
+    serde_cbor::ser::write_typed_frame(&mut stream, 2, &"hi").unwrap();
+    serde_cbor::ser::write_typed_frame(&mut stream, 1, &1000u32).unwrap();
+    assert_eq!(stream, b"\x02\x00\x00\x00\x03\x62hi\x01\x00\x00\x00\x03\x19\x03\xe8");
+    let mut reader = &stream[..];
+    let (kind, value) = de::read_typed_frame(&mut reader, dispatch).unwrap().unwrap();
+    assert_eq!((kind, value.downcast_ref::<String>()), (2, Some(&"hi".to_owned())));
+    let (kind, value) = de::read_typed_frame(&mut reader, dispatch).unwrap().unwrap();
+    assert_eq!((kind, value.downcast_ref::<u32>()), (1, Some(&1000)));
+    assert!(de::read_typed_frame(&mut reader, dispatch).unwrap().is_none());
+
+    match de::read_typed_frame(&b"\x07\x00\x00\x00\x01\x00"[..], dispatch) {
+        Err(error::Error::UnknownFrameType(7)) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    assert!(de::read_typed_frame(&b"\x01\x00\x00\x00\x03\x19\x03"[..], dispatch).is_err());
+    assert!(de::read_typed_frame(&b"\x01\x00\x00\x00\x02\x01\x02"[..], dispatch).is_err());
+    assert!(de::read_typed_frame(&b"\x02\x00\x00\x00\x01\x01"[..], dispatch).is_err());
+    assert!(de::read_typed_frame(&b"\x01\xff\xff\xff\xff\x01"[..], dispatch).is_err());
+}
+
+#[test]
+fn test_null_as_empty_collection() {
+    let strict = |input: &'static [u8]| {
+        de::Deserializer::new(input).null_as_empty_collection(false)
+    };
+    assert_eq!(de::from_slice::<Vec<u8>>(b"\xf6").unwrap(), vec![]);
+    assert_eq!(de::from_slice::<HashMap<String, u8>>(b"\xf6").unwrap(), HashMap::new());
+    assert_eq!(de::from_slice::<Vec<Vec<u8>>>(b"\x82\xf6\x81\x01").unwrap(), vec![vec![], vec![1]]);
+    assert_eq!(de::from_slice::<Vec<u8>>(b"\xf7").unwrap(), vec![]);
+    assert!(de::from_slice::<Vec<u8>>(b"\xf5").is_err());
+    assert!(de::from_slice::<u8>(b"\xf6").is_err());
+
+    assert!(decode::<Vec<u8>>(strict(b"\xf6")).is_err());
+    assert!(decode::<HashMap<String, u8>>(strict(b"\xf6")).is_err());
+    assert!(decode::<ByteBuf>(strict(b"\xf6")).is_err());
+    assert!(decode::<Vec<u8>>(strict(b"\xf7")).is_err());
+    assert_eq!(decode::<Option<Vec<u8>>>(strict(b"\xf6")).unwrap(), None);
+    assert_eq!(decode::<Vec<u8>>(strict(b"\x82\x01\x02")).unwrap(), vec![1, 2]);
+}
+
+#[test]
+fn test_unknown_simple_as_null() {
+    let slice = b"\x84\xe0\xf3\xf8\xff\xf6";
+    let mut de = de::Deserializer::new(&slice[..]).unknown_simple_as_null(true);
+    let value: Value = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, Value::Array(vec![Value::Null; 4]));
+    let mut de = de::Deserializer::new(&slice[..]).unknown_simple_as_null(true);
+    let value: Vec<Option<u8>> = Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(value, vec![None; 4]);
+    assert!(de.end().is_ok());
+    let mut de = de::Deserializer::new(&b"\xfc"[..]).unknown_simple_as_null(true);
+    assert!(Value::deserialize(&mut de).is_err());
+    assert!(de::from_slice::<Value>(b"\xe0").is_err());
+    assert!(de::from_slice::<Option<u8>>(b"\xf8\xff").is_err());
+}
+
+#[test]
+fn test_annotated() {
+    fn scalar(width: u8) -> Annotation {
+        Annotation { width: width, indefinite: false, children: vec![] }
+    }
+
+    let slice = b"\x9f\x18\x01\xfa\x3f\x80\x00\x00\x7f\x61a\x78\x01b\xff\xb9\x00\x01\xd8\x20\x40\xf6\xff";
+    let (value, annotation) = de::from_slice_annotated(slice).unwrap();
+    let mut object = HashMap::new();
+    object.insert(ObjectKey::Bytes(vec![]), Value::Null);
+    assert_eq!(value, Value::Array(vec![
+        Value::U64(1),
+        Value::F64(1.0),
+        Value::String("ab".to_owned()),
+        Value::Object(object),
+    ]));
+    assert_eq!(annotation, Annotation {
+        width: 0,
+        indefinite: true,
+        children: vec![
+            scalar(1),
+            scalar(4),
+            Annotation { width: 0, indefinite: true, children: vec![scalar(0), scalar(1)] },
+            Annotation {
+                width: 2,
+                indefinite: false,
+                children: vec![Annotation { width: 1, indefinite: false, children: vec![scalar(0)] }, scalar(0)],
+            },
+        ],
+    });
+    assert!(de::from_slice_annotated(b"\x82\x01").is_err());
+
+    let mut deep = vec![0x81; 1000000];
+    deep.push(0x00);
+    let mut de = de::Deserializer::from_slice(&deep).max_depth(Some(16));
+    match de.deserialize_annotated() {
+        Err(error::Error::DepthLimitExceeded) => {}
+        other => panic!("{:?}", other),
+    }
+    let mut de = de::Deserializer::from_slice(b"\x9f\x01\x02\x03\xff").max_total_items(Some(2));
+    assert!(de.deserialize_annotated().is_err());
 }
 
 #[test]
@@ -99,6 +825,36 @@ fn test_indefinite_list() {
     assert_eq!(value.unwrap(), Value::Array(vec![Value::U64(1), Value::U64(2), Value::U64(3)]));
 }
 
+#[test]
+fn test_collections() {
+    for input in &[&b"\x83\x03\x01\x02"[..], b"\x9f\x03\x01\x02\xff"] {
+        let value: VecDeque<u8> = de::from_slice(input).unwrap();
+        assert_eq!(value.into_iter().collect::<Vec<_>>(), vec![3, 1, 2]);
+        let value: LinkedList<u8> = de::from_slice(input).unwrap();
+        assert_eq!(value.into_iter().collect::<Vec<_>>(), vec![3, 1, 2]);
+        let value: BinaryHeap<u8> = de::from_slice(input).unwrap();
+        assert_eq!(value.into_sorted_vec(), vec![1, 2, 3]);
+        let value: BTreeSet<u8> = de::from_slice(input).unwrap();
+        assert_eq!(value.into_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+        let value: HashSet<u8> = de::from_slice(input).unwrap();
+        assert_eq!(value.len(), 3);
+    }
+    let value: VecDeque<u8> = de::from_slice(b"\x80").unwrap();
+    assert!(value.is_empty());
+    let value: BinaryHeap<u8> = de::from_slice(b"\x9f\xff").unwrap();
+    assert!(value.is_empty());
+    let value: BTreeSet<u8> = de::from_slice(b"\x82\x01\x01").unwrap();
+    assert_eq!(value.len(), 1);
+    let value: VecDeque<VecDeque<u8>> = de::from_slice(b"\x82\x9f\x01\xff\x80").unwrap();
+    assert_eq!(value.into_iter().map(|v| v.into_iter().collect()).collect::<Vec<Vec<u8>>>(), vec![vec![1], vec![]]);
+
+    assert!(de::from_slice::<VecDeque<u8>>(b"\x9a\x00\x07\xa1\x20\x01").is_err());
+    assert!(de::from_slice::<BinaryHeap<u8>>(b"\x9a\x00\x07\xa1\x20\x01").is_err());
+    assert!(de::from_slice::<LinkedList<u8>>(b"\x9f\x01\x02").is_err());
+    assert!(de::from_slice::<VecDeque<u8>>(b"\x82\x01").is_err());
+    assert!(de::from_slice::<BTreeSet<u8>>(b"\x82\x01\x02\x03").is_err());
+}
+
 #[test]
 fn test_indefinite_string() {
     let value: error::Result<Value> = de::from_slice(b"\x7f\x65Mary \x64Had \x62a \x67Little \x64Lamb\xff");
@@ -124,6 +880,197 @@ fn test_self_describing() {
     assert_eq!(value.unwrap(), Value::String("foobar".to_owned()));
 }
 
+#[test]
+fn test_tagged_value() {
+    let value: Value = de::from_slice(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0]).unwrap();
+    assert_eq!(value, Value::Tag(1, Box::new(Value::U64(1363896240))));
+    assert_eq!(value.as_tag(), Some((1, &Value::U64(1363896240))));
+    let value: Value = de::from_slice(&[0x82, 0xd8, 0x20, 0x61, 0x61, 0xc2, 0xc3, 0x40]).unwrap();
+    assert_eq!(value, Value::Array(vec![
+        Value::Tag(32, Box::new(Value::String("a".to_owned()))),
+        Value::Tag(2, Box::new(Value::Tag(3, Box::new(Value::Bytes(vec![]))))),
+    ]));
+    let value: u64 = de::from_slice(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0]).unwrap();
+    assert_eq!(value, 1363896240);
+    // Tags have no indefinite length.
+    match de::from_slice::<Value>(b"\xdf\x01") {
+        Err(error::Error::Syntax) => {}
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+    match de::from_slice::<u64>(b"\xdf\x01") {
+        Err(error::Error::Syntax) => {}
+        other => panic!("expected a syntax error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_duplicate_tags() {
+    let tags = |input: &'static [u8], duplicate_tags| {
+        de::Deserializer::new(input).duplicate_tags(duplicate_tags)
+    };
+    let one = || Box::new(Value::U64(1));
+    let slice = b"\xc1\xc1\xd8\x01\xc2\xd9\x00\x02\x01";
+    let value: Value = decode(tags(slice, de::DuplicateTags::Preserve)).unwrap();
+    assert_eq!(value, Value::Tag(1, Box::new(Value::Tag(1, Box::new(Value::Tag(1, Box::new(
+        Value::Tag(2, Box::new(Value::Tag(2, one()))))))))));
+    let value: Value = decode(tags(slice, de::DuplicateTags::Collapse)).unwrap();
+    assert_eq!(value, Value::Tag(1, Box::new(Value::Tag(2, one()))));
+    match decode::<Value>(tags(slice, de::DuplicateTags::Reject)) {
+        Err(error::Error::DuplicateTag(1)) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(decode::<u8>(tags(slice, de::DuplicateTags::Collapse)).unwrap(), 1);
+    let rational = b"\xd8\x1e\xd8\x1e\x82\x01\x02";
+    let value: (i64, u64) = decode(tags(rational, de::DuplicateTags::Collapse)).unwrap();
+    assert_eq!(value, (1, 2));
+    assert!(decode::<(i64, u64)>(tags(rational, de::DuplicateTags::Preserve)).is_err());
+    let value: Value = decode(tags(b"\xc1\xc2\xc1\x01", de::DuplicateTags::Reject)).unwrap();
+    assert_eq!(value, Value::Tag(1, Box::new(Value::Tag(2, Box::new(Value::Tag(1, one()))))));
+}
+
+#[cfg(feature = "fixed_point")]
+#[test]
+fn test_fixed_point() {
+    use serde_cbor::fixed::{I8F8, I16F16, I32F32};
+
+    assert_eq!(de::from_slice::<I16F16>(b"\xf9\x3e\x00").unwrap().to_bits(), 0x1_8000);
+    assert_eq!(de::from_slice::<I16F16>(b"\xfa\xc0\x49\x0f\xdb").unwrap().to_bits(), -205887);
+    assert_eq!(de::from_slice::<I16F16>(b"\xfb\x40\x09\x21\xfb\x54\x44\x2d\x18").unwrap().to_bits(),
+               205887);
+    assert_eq!(de::from_slice::<I16F16>(b"\x19\x7f\xff").unwrap().to_bits(), 0x7fff_0000);
+    assert_eq!(de::from_slice::<I16F16>(b"\x39\x7f\xff").unwrap().to_bits(), -0x8000_0000);
+    assert_eq!(de::from_slice::<I32F32>(b"\xf9\x80\x00").unwrap().to_bits(), 0);
+    // Halfway between two numbers the one with an even last bit wins.
+    assert_eq!(de::from_slice::<I8F8>(b"\xfa\x3c\x20\x00\x00").unwrap().to_bits(), 0x02);
+    assert_eq!(de::from_slice::<I8F8>(b"\xfa\x3c\x60\x00\x00").unwrap().to_bits(), 0x04);
+    assert_eq!(de::from_slice::<I8F8>(b"\xfa\xbc\x20\x00\x00").unwrap().to_bits(), -0x02);
+    assert_eq!(de::from_slice::<I8F8>(b"\xf9\x00\x01").unwrap().to_bits(), 0);
+    assert_eq!(de::from_slice::<I8F8>(b"\xf9\xd8\x00").unwrap().to_bits(), -0x8000);
+    for input in &[&b"\xf9\x58\x00"[..], b"\xf9\x7e\x00", b"\xf9\xfc\x00", b"\x19\x01\x00",
+                   b"\xfb\x7f\xef\xff\xff\xff\xff\xff\xff"] {
+        assert!(de::from_slice::<I8F8>(input).is_err());
+    }
+    assert!(de::from_slice::<I8F8>(b"\x61a").is_err());
+}
+
+#[test]
+fn test_rational() {
+    let value: (i64, u64) = de::from_slice(&[0xd8, 0x1e, 0x82, 0x20, 0x03]).unwrap();
+    assert_eq!(value, (-1, 3));
+    let value: Vec<i32> = de::from_slice(&[0xd8, 0x1e, 0x82, 0x19, 0x01, 0x00, 0x18, 0x64]).unwrap();
+    assert_eq!(value, vec![256, 100]);
+    let value: error::Result<(i64, u64)> = de::from_slice(&[0xd8, 0x1e, 0x82, 0x01, 0x00]);
+    assert!(value.is_err());
+    let value: error::Result<(i64, u64)> = de::from_slice(&[0xd8, 0x1e, 0x83, 0x01, 0x02, 0x03]);
+    assert!(value.is_err());
+    let value: error::Result<(i64, u64)> = de::from_slice(&[0xd8, 0x1e, 0x82, 0x01, 0x22]);
+    assert!(value.is_err());
+    let value: error::Result<(i64, u64)> = de::from_slice(&[0xd8, 0x1e, 0x9f, 0x01, 0x02, 0xff]);
+    assert!(value.is_err());
+    let input = [0xd8, 0x1e, 0x82, 0xc2, 0x41, 0x01, 0x02];
+    let value: error::Result<(i64, u64)> = de::from_slice(&input);
+    assert!(value.is_err());
+
+    // A `Value` keeps the tag but is checked the same way.
+    let value: Value = de::from_slice(&[0xd8, 0x1e, 0x82, 0x20, 0x03]).unwrap();
+    let array = Value::Array(vec![Value::I64(-1), Value::U64(3)]);
+    assert_eq!(value, Value::Tag(30, Box::new(array)));
+    for input in &[&[0xd8, 0x1e, 0x82, 0x01, 0x00][..],
+                   &[0xd8, 0x1e, 0x83, 0x01, 0x02, 0x03],
+                   &[0xd8, 0x1e, 0x82, 0x01, 0x22],
+                   &[0xd8, 0x1e, 0x9f, 0x01, 0x02, 0xff],
+                   &[0xd8, 0x1e, 0x82, 0x3b, 0x80, 0, 0, 0, 0, 0, 0, 0, 0x01],
+                   &input] {
+        match de::from_slice::<Value>(input) {
+            Err(error::Error::Syntax) => {}
+            other => panic!("{:?}: {:?}", input, other),
+        }
+    }
+}
+
+#[test]
+fn test_small_bignums() {
+    let value: u64 = de::from_slice(b"\xc2\x41\x05").unwrap();
+    assert_eq!(value, 5);
+    let value: u8 = de::from_slice(b"\xc2\x43\x00\x00\x05").unwrap();
+    assert_eq!(value, 5);
+    let value: i64 = de::from_slice(b"\xc3\x41\x05").unwrap();
+    assert_eq!(value, -6);
+    let value: u64 = de::from_slice(b"\xc2\x48\xff\xff\xff\xff\xff\xff\xff\xff").unwrap();
+    assert_eq!(value, ::std::u64::MAX);
+    let value: i64 = de::from_slice(b"\xc3\x40").unwrap();
+    assert_eq!(value, -1);
+    let value: ByteBuf = de::from_slice(b"\xc2\x49\x01\x00\x00\x00\x00\x00\x00\x00\x00").unwrap();
+    assert_eq!(value.len(), 9);
+    assert!(de::from_slice::<i64>(b"\xc3\x48\x80\x00\x00\x00\x00\x00\x00\x00").is_err());
+
+    let mut de = de::Deserializer::new(&b"\xc2\x41\x05"[..]).strict_canonical(true);
+    match u64::deserialize(&mut de) {
+        Err(error::Error::NonCanonical) => {}
+        other => panic!("{:?}", other),
+    }
+    let mut de = de::Deserializer::new(&b"\xc2\x49\x01\x00\x00\x00\x00\x00\x00\x00\x00"[..])
+        .strict_canonical(true);
+    assert_eq!(ByteBuf::deserialize(&mut de).unwrap().len(), 9);
+    assert!(!de::from_slice_checked::<u64>(b"\xc2\x41\x05").unwrap().1);
+
+    // Other types still see the byte string, but it is not canonical either.
+    let value: ByteBuf = de::from_slice(b"\xc2\x41\x05").unwrap();
+    assert_eq!(&value[..], [5]);
+    let value: Value = de::from_slice(b"\xc3\x41\x05").unwrap();
+    assert_eq!(value, Value::Tag(3, Box::new(Value::Bytes(vec![5]))));
+    let strict = |input: &'static [u8]| de::Deserializer::new(input).strict_canonical(true);
+    match decode::<ByteBuf>(strict(b"\xc2\x41\x05")) {
+        Err(error::Error::NonCanonical) => {}
+        other => panic!("{:?}", other),
+    }
+    match decode::<Value>(strict(b"\xc2\x41\x05")) {
+        Err(error::Error::NonCanonical) => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(!de::from_slice_checked::<Value>(b"\xc2\x41\x05").unwrap().1);
+    assert!(!de::from_slice_checked::<Value>(b"\x81\xc3\x40").unwrap().1);
+    let input = b"\xc2\x49\x01\x00\x00\x00\x00\x00\x00\x00\x00";
+    let value = decode::<Value>(strict(input)).unwrap();
+    assert_eq!(value, Value::Tag(2, Box::new(Value::Bytes(input[2..].to_vec()))));
+    assert!(de::from_slice_checked::<Value>(input).unwrap().1);
+}
+
+#[test]
+fn test_days_since_epoch() {
+    let value: i64 = de::from_slice(&[0xd8, 0x64, 0x19, 0x4b, 0x9b]).unwrap();
+    assert_eq!(value, 19355);
+    let value: i32 = de::from_slice(&[0xd8, 0x64, 0x39, 0x01, 0x6c]).unwrap();
+    assert_eq!(value, -365);
+    let value: i64 = de::from_slice(&[0xd8, 0x64, 0x00]).unwrap();
+    assert_eq!(value, 0);
+    let value: error::Result<String> = de::from_slice(b"\xd8\x64\x6a2023-01-01");
+    assert!(value.is_err());
+    let value: error::Result<f64> = de::from_slice(&[0xd8, 0x64, 0xf9, 0x3c, 0x00]);
+    assert!(value.is_err());
+}
+
+#[test]
+fn test_typed_array() {
+    let value: Vec<u32> = de::from_slice(b"\xd8\x42\x48\x00\x00\x00\x01\x00\x01\x00\x00").unwrap();
+    assert_eq!(value, vec![1, 65536]);
+    let value: Vec<u16> = de::from_slice(b"\xd8\x45\x44\x01\x00\x00\x01").unwrap();
+    assert_eq!(value, vec![1, 256]);
+    let value: Vec<i8> = de::from_slice(b"\xd8\x48\x42\xff\x7f").unwrap();
+    assert_eq!(value, vec![-1, 127]);
+    let value: Vec<i32> = de::from_slice(b"\xd8\x4e\x44\xfe\xff\xff\xff").unwrap();
+    assert_eq!(value, vec![-2]);
+    let value: Vec<f32> = de::from_slice(b"\xd8\x51\x48\x3f\xc0\x00\x00\xff\x80\x00\x00").unwrap();
+    assert_eq!(value, vec![1.5, std::f32::NEG_INFINITY]);
+    let value: Vec<f64> = de::from_slice(b"\xd8\x50\x42\x41\x00").unwrap();
+    assert_eq!(value, vec![2.5]);
+    let value: Vec<f64> = de::from_slice(b"\xd8\x56\x48\x00\x00\x00\x00\x00\x00\x04\x40").unwrap();
+    assert_eq!(value, vec![2.5]);
+    assert!(de::from_slice::<Vec<u16>>(b"\xd8\x41\x43\x00\x01\x02").is_err());
+    assert!(de::from_slice::<Vec<u8>>(b"\xd8\x40\x82\x01\x02").is_err());
+    assert!(de::from_slice::<Vec<f64>>(b"\xd8\x53\x40").is_err());
+}
+
 
 #[test]
 fn test_f16() {
@@ -197,3 +1144,549 @@ fn test_option_none_roundtrip() {
 
     assert_eq!(obj1, obj2.unwrap());
 }
+
+// Mirrors the code `#[derive(Deserialize)]` generates, `serde_macros` is unstable only.
+#[derive(Debug, PartialEq)]
+enum Shape {
+    Point,
+    Circle(u32),
+    Rect(u32, u32),
+    Empty,
+}
+
+impl Deserialize for Shape {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Shape, D::Error> {
+        enum Field {
+            Point,
+            Circle,
+            Rect,
+            Empty,
+        }
+
+        impl Deserialize for Field {
+            fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Field, D::Error> {
+                struct FieldVisitor;
+
+                impl Visitor for FieldVisitor {
+                    type Value = Field;
+
+                    fn visit_str<E: serde::de::Error>(&mut self, value: &str) -> Result<Field, E> {
+                        match value {
+                            "Point" => Ok(Field::Point),
+                            "Circle" => Ok(Field::Circle),
+                            "Rect" => Ok(Field::Rect),
+                            "Empty" => Ok(Field::Empty),
+                            _ => Err(E::unknown_variant(value)),
+                        }
+                    }
+                }
+
+                deserializer.deserialize_struct_field(FieldVisitor)
+            }
+        }
+
+        struct ShapeVisitor;
+
+        impl EnumVisitor for ShapeVisitor {
+            type Value = Shape;
+
+            fn visit<V: VariantVisitor>(&mut self, mut visitor: V) -> Result<Shape, V::Error> {
+                match try!(visitor.visit_variant()) {
+                    Field::Point => visitor.visit_unit().map(|()| Shape::Point),
+                    Field::Circle => visitor.visit_newtype().map(Shape::Circle),
+                    Field::Rect => visitor.visit_tuple(2, TupleVisitor2::new())
+                        .map(|(w, h)| Shape::Rect(w, h)),
+                    Field::Empty => visitor.visit_unit().map(|()| Shape::Empty),
+                }
+            }
+        }
+
+        const VARIANTS: &'static [&'static str] = &["Point", "Circle", "Rect", "Empty"];
+        deserializer.deserialize_enum("Shape", VARIANTS, ShapeVisitor)
+    }
+}
+
+#[test]
+fn test_enum_variant_name() {
+    let point: Shape = de::from_slice(b"\x65Point").unwrap();
+    assert_eq!(point, Shape::Point);
+    let point: Shape = de::from_slice(b"\x81\x65Point").unwrap();
+    assert_eq!(point, Shape::Point);
+    let circle: Shape = de::from_slice(b"\x82\x66Circle\x05").unwrap();
+    assert_eq!(circle, Shape::Circle(5));
+    let rect: Shape = de::from_slice(b"\x83\x64Rect\x02\x03").unwrap();
+    assert_eq!(rect, Shape::Rect(2, 3));
+    let rect: Shape = de::from_slice(b"\x9f\x64Rect\x02\x03\xff").unwrap();
+    assert_eq!(rect, Shape::Rect(2, 3));
+}
+
+#[test]
+fn test_enum_variant_index() {
+    let point: Shape = de::from_slice(b"\x81\x00").unwrap();
+    assert_eq!(point, Shape::Point);
+    let circle: Shape = de::from_slice(b"\x82\x01\x05").unwrap();
+    assert_eq!(circle, Shape::Circle(5));
+    let rect: Shape = de::from_slice(b"\x83\x02\x02\x03").unwrap();
+    assert_eq!(rect, Shape::Rect(2, 3));
+    assert!(de::from_slice::<Shape>(b"\x82\x04\x05").is_err());
+}
+
+#[test]
+fn test_enum_bare_index() {
+    let empty: Shape = de::from_slice(b"\x03").unwrap();
+    assert_eq!(empty, Shape::Empty);
+    let point: Shape = de::from_slice(b"\x00").unwrap();
+    assert_eq!(point, Shape::Point);
+    assert!(de::from_slice::<Shape>(b"\x02").is_err());
+    assert!(de::from_slice::<Shape>(b"\x18\x04").is_err());
+}
+
+#[test]
+fn test_enum_single_entry_map() {
+    assert_eq!(de::from_slice::<Shape>(b"\xa1\x03\xf6").unwrap(), Shape::Empty);
+    assert_eq!(de::from_slice::<Shape>(b"\xa1\x01\x05").unwrap(), Shape::Circle(5));
+    assert_eq!(de::from_slice::<Shape>(b"\xa1\x02\x82\x03\x04").unwrap(), Shape::Rect(3, 4));
+    assert_eq!(de::from_slice::<Shape>(b"\xa1\x66Circle\x05").unwrap(), Shape::Circle(5));
+    assert_eq!(de::from_slice::<Shape>(b"\xbf\x01\x05\xff").unwrap(), Shape::Circle(5));
+    assert!(de::from_slice::<Shape>(b"\xa1\x03\x00").is_err());
+    assert!(de::from_slice::<Shape>(b"\xa2\x01\x05\x03\xf6").is_err());
+    assert!(de::from_slice::<Shape>(b"\xbf\x01\x05\x03\xf6\xff").is_err());
+    assert!(de::from_slice::<Shape>(b"\xa1\x04\xf6").is_err());
+}
+
+#[test]
+fn test_enum_variant_length() {
+    assert!(de::from_slice::<Shape>(b"\x80").is_err());
+    assert!(de::from_slice::<Shape>(b"\x82\x65Point\x00").is_err());
+    assert!(de::from_slice::<Shape>(b"\x83\x66Circle\x05\x06").is_err());
+    assert!(de::from_slice::<Shape>(b"\x82\x64Rect\x02").is_err());
+    assert!(de::from_slice::<Shape>(b"\x9f\x66Circle\x05\x06\xff").is_err());
+}
+
+#[test]
+fn test_enum_variant_tag() {
+    let shape = |input: &'static [u8], variant_tags| {
+        decode::<Shape>(de::Deserializer::new(input).variant_tags(variant_tags))
+    };
+    let inline = de::VariantTags::Inline;
+    assert_eq!(shape(b"\xc1\x05", inline).unwrap(), Shape::Circle(5));
+    assert_eq!(shape(b"\xc2\x82\x02\x03", inline).unwrap(), Shape::Rect(2, 3));
+    assert_eq!(shape(b"\xc3\xf6", inline).unwrap(), Shape::Empty);
+    assert!(shape(b"\xc4\x05", inline).is_err());
+    assert!(shape(b"\xc1\x82\x05\x06", inline).is_err());
+
+    let array = de::VariantTags::Array;
+    assert_eq!(shape(b"\xc1\x81\x05", array).unwrap(), Shape::Circle(5));
+    assert_eq!(shape(b"\xc1\x9f\x05\xff", array).unwrap(), Shape::Circle(5));
+    assert_eq!(shape(b"\xc2\x82\x02\x03", array).unwrap(), Shape::Rect(2, 3));
+    assert_eq!(shape(b"\xc3\x80", array).unwrap(), Shape::Empty);
+    assert!(shape(b"\xc1\x05", array).is_err());
+
+    assert!(shape(b"\xc1\x05", de::VariantTags::Reject).is_err());
+    assert!(de::from_slice::<Shape>(b"\xc1\x05").is_err());
+}
+
+// Mirrors `#[derive(Deserialize)]` with `#[serde(rename = "...")]` on both fields.
+#[derive(Debug, PartialEq)]
+struct Person {
+    first_name: String,
+    age: u8,
+}
+
+impl Deserialize for Person {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Person, D::Error> {
+        enum Field {
+            FirstName,
+            Age,
+            Ignore,
+        }
+
+        impl Deserialize for Field {
+            fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Field, D::Error> {
+                struct FieldVisitor;
+
+                impl Visitor for FieldVisitor {
+                    type Value = Field;
+
+                    fn visit_str<E: serde::de::Error>(&mut self, value: &str) -> Result<Field, E> {
+                        match value {
+                            "firstName" => Ok(Field::FirstName),
+                            "AGE" => Ok(Field::Age),
+                            _ => Ok(Field::Ignore),
+                        }
+                    }
+                }
+
+                deserializer.deserialize_struct_field(FieldVisitor)
+            }
+        }
+
+        struct PersonVisitor;
+
+        impl Visitor for PersonVisitor {
+            type Value = Person;
+
+            fn visit_map<V: MapVisitor>(&mut self, mut visitor: V) -> Result<Person, V::Error> {
+                let mut first_name = None;
+                let mut age = None;
+                while let Some(field) = try!(visitor.visit_key()) {
+                    match field {
+                        Field::FirstName => first_name = Some(try!(visitor.visit_value())),
+                        Field::Age => age = Some(try!(visitor.visit_value())),
+                        Field::Ignore => {
+                            try!(visitor.visit_value::<IgnoredAny>());
+                        }
+                    }
+                }
+                try!(visitor.end());
+                Ok(Person {
+                    first_name: match first_name {
+                        Some(first_name) => first_name,
+                        None => try!(visitor.missing_field("firstName")),
+                    },
+                    age: match age {
+                        Some(age) => age,
+                        None => try!(visitor.missing_field("AGE")),
+                    },
+                })
+            }
+        }
+
+        const FIELDS: &'static [&'static str] = &["firstName", "AGE"];
+        deserializer.deserialize_struct("Person", FIELDS, PersonVisitor)
+    }
+}
+
+#[test]
+fn test_renamed_fields() {
+    let expected = Person { first_name: "Ada".to_owned(), age: 36 };
+    let person: Person = de::from_slice(b"\xa2\x69firstName\x63Ada\x63AGE\x18\x24").unwrap();
+    assert_eq!(person, expected);
+    let person: Person = de::from_slice(
+        b"\xa3\x63AGE\x18\x24\x63age\x82\x01\x02\x7f\x65first\x64Name\xff\x63Ada").unwrap();
+    assert_eq!(person, expected);
+    assert!(de::from_slice::<Person>(b"\xa2\x6afirst_name\x63Ada\x63AGE\x18\x24").is_err());
+}
+
+#[test]
+fn test_on_progress() {
+    let mut input = b"\x99\x03\xe8".to_vec();
+    input.extend_from_slice(&[0x00; 1000]);
+    let calls = Recorder::new();
+    let seen = calls.clone();
+    let de = de::Deserializer::new(&input[..]).on_progress(100, move |items| {
+        seen.push(items);
+        ControlFlow::Continue(())
+    });
+    decode::<IgnoredAny>(de).unwrap();
+    assert_eq!(calls.take(), (1..11).map(|n| n * 100).collect::<Vec<_>>());
+
+    let mut de = de::Deserializer::new(&input[..]).on_progress(10, |items| {
+        if items < 250 { ControlFlow::Continue(()) } else { ControlFlow::Break(()) }
+    });
+    match IgnoredAny::deserialize(&mut de) {
+        Err(error::Error::Aborted) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_trace() {
+    let headers = Recorder::new();
+    let seen = headers.clone();
+    let de = de::Deserializer::new(&b"\x82\x18\x64\xa1\x61a\xc1\xf5"[..]).trace(move |header| {
+        seen.push((header.offset, header.depth, header.major, header.info));
+    });
+    let value: Value = decode(de).unwrap();
+    let expected = de::from_slice::<Value>(b"\x82\x18\x64\xa1\x61a\xc1\xf5").unwrap();
+    assert_eq!(value, expected);
+    assert_eq!(headers.take(),
+               vec![(0, 0, 4, 2), (1, 1, 0, 24), (3, 1, 5, 1), (4, 2, 3, 1), (6, 2, 6, 1), (7, 3, 7, 21)]);
+
+    let headers = Recorder::new();
+    let seen = headers.clone();
+    let de = de::Deserializer::new(&b"\x83\x01\x17\x18\x18"[..]).trace(move |_| seen.push(()));
+    assert_eq!(decode::<Vec<u8>>(de).unwrap(), vec![1, 23, 24]);
+    assert_eq!(headers.take().len(), 4);
+}
+
+#[test]
+fn test_skip_limits() {
+    fn person(hostile: &[u8], limits: (Option<usize>, Option<usize>, Option<usize>))
+        -> error::Result<Person>
+    {
+        let mut input = b"\xa3\x69firstName\x63Ada\x61x".to_vec();
+        input.extend_from_slice(hostile);
+        input.extend_from_slice(b"\x63AGE\x18\x24");
+        decode(de::Deserializer::new(&input[..])
+            .max_depth(limits.0)
+            .max_indefinite_containers(limits.1)
+            .max_total_items(limits.2))
+    }
+
+    let mut deep = vec![0x81; 100000];
+    deep.push(0x00);
+    match person(&deep, (Some(64), None, None)) {
+        Err(error::Error::DepthLimitExceeded) => {}
+        other => panic!("{:?}", other),
+    }
+    let tags = vec![0xc6; 100000];
+    match person(&tags, (Some(64), None, None)) {
+        Err(error::Error::DepthLimitExceeded) => {}
+        other => panic!("{:?}", other),
+    }
+    let mut indefinite = vec![0x9f; 8];
+    indefinite.extend_from_slice(&[0xff; 8]);
+    match person(&indefinite, (None, Some(4), None)) {
+        Err(error::Error::TooManyIndefiniteContainers) => {}
+        other => panic!("{:?}", other),
+    }
+    let mut chunks = vec![0x5f];
+    chunks.extend_from_slice(&[0x40; 10000]);
+    chunks.push(0xff);
+    let mut de = de::Deserializer::new(&chunks[..]).max_string_chunks(Some(100));
+    match IgnoredAny::deserialize(&mut de) {
+        Err(error::Error::TooManyChunks) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    let mut de = de::Deserializer::new(&b"\x7f\x61a\x60\x61b\xff"[..]).max_string_chunks(Some(3));
+    assert_eq!(String::deserialize(&mut de).unwrap(), "ab");
+    let mut de = de::Deserializer::new(&b"\x7f\x61a\x60\x61b\xff"[..]).max_string_chunks(Some(2));
+    assert!(String::deserialize(&mut de).is_err());
+    let mut wide = b"\x99\x03\xe8".to_vec();
+    wide.extend_from_slice(&[0x00; 1000]);
+    match person(&wide, (None, None, Some(100))) {
+        Err(error::Error::TooManyItems) => {}
+        other => panic!("{:?}", other),
+    }
+
+    let expected = Person { first_name: "Ada".to_owned(), age: 36 };
+    let limits = (Some(9), Some(8), Some(1011));
+    assert_eq!(person(&deep[99992..], limits).unwrap(), expected);
+    assert_eq!(person(&indefinite, limits).unwrap(), expected);
+    assert_eq!(person(&wide, limits).unwrap(), expected);
+}
+
+#[test]
+fn test_max_map_entries() {
+    let entries = |input: &'static [u8], max| {
+        de::Deserializer::new(input).max_map_entries(Some(max))
+    };
+    match decode::<HashMap<u8, u8>>(entries(b"\xb9\x03\xe8\x00\x00", 100)) {
+        Err(error::Error::TooManyMapEntries) => {}
+        other => panic!("{:?}", other),
+    }
+    let indefinite = b"\xbf\x01\x01\x02\x02\x03\x03\xff";
+    match decode::<HashMap<u8, u8>>(entries(indefinite, 2)) {
+        Err(error::Error::TooManyMapEntries) => {}
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(decode::<HashMap<u8, u8>>(entries(indefinite, 3)).unwrap().len(), 3);
+    match decode::<Vec<Value>>(entries(b"\x82\xa0\xa2\x01\x01\x02\x02", 1)) {
+        Err(error::Error::TooManyMapEntries) => {}
+        other => panic!("{:?}", other),
+    }
+    match decode::<IgnoredAny>(entries(b"\x81\xbf\x01\x01\x02\x02\xff", 1)) {
+        Err(error::Error::TooManyMapEntries) => {}
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+    assert!(decode::<Vec<u8>>(entries(b"\x83\x01\x02\x03", 1)).is_ok());
+}
+
+#[derive(Debug, PartialEq)]
+struct Envelope {
+    route: String,
+    payload: RawValue,
+}
+
+impl Deserialize for Envelope {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Envelope, D::Error> {
+        struct EnvelopeVisitor;
+
+        impl Visitor for EnvelopeVisitor {
+            type Value = Envelope;
+
+            fn visit_map<V: MapVisitor>(&mut self, mut visitor: V) -> Result<Envelope, V::Error> {
+                let mut route = None;
+                let mut payload = None;
+                while let Some(field) = try!(visitor.visit_key::<String>()) {
+                    match &field[..] {
+                        "route" => route = Some(try!(visitor.visit_value())),
+                        "payload" => payload = Some(try!(visitor.visit_value())),
+                        _ => {
+                            try!(visitor.visit_value::<IgnoredAny>());
+                        }
+                    }
+                }
+                try!(visitor.end());
+                Ok(Envelope {
+                    route: match route {
+                        Some(route) => route,
+                        None => try!(visitor.missing_field("route")),
+                    },
+                    payload: match payload {
+                        Some(payload) => payload,
+                        None => try!(visitor.missing_field("payload")),
+                    },
+                })
+            }
+        }
+
+        const FIELDS: &'static [&'static str] = &["route", "payload"];
+        deserializer.deserialize_struct("Envelope", FIELDS, EnvelopeVisitor)
+    }
+}
+
+impl serde::Serialize for Envelope {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        struct EnvelopeVisitor<'a>(&'a Envelope, u8);
+
+        impl<'a> serde::ser::MapVisitor for EnvelopeVisitor<'a> {
+            fn visit<S: serde::Serializer>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error> {
+                self.1 += 1;
+                match self.1 {
+                    1 => serializer.serialize_struct_elt("route", &self.0.route).map(Some),
+                    2 => serializer.serialize_struct_elt("payload", &self.0.payload).map(Some),
+                    _ => Ok(None),
+                }
+            }
+        }
+
+        serializer.serialize_struct("Envelope", EnvelopeVisitor(self, 0))
+    }
+}
+
+#[test]
+fn test_raw_value() {
+    let payload = b"\xa2\x61a\x9f\x01\xc1\x02\xff\x61b\x7f\x61x\xff";
+    let mut input = b"\xa2\x65route\x63foo\x67payload".to_vec();
+    input.extend_from_slice(payload);
+    let envelope: Envelope = de::from_slice(&input).unwrap();
+    assert_eq!(envelope.route, "foo");
+    assert_eq!(envelope.payload.as_bytes(), &payload[..]);
+    let mut output = Vec::new();
+    serde::Serialize::serialize(&envelope, &mut Serializer::new(&mut output).canonical()).unwrap();
+    assert_eq!(output, input);
+    let raw: RawValue = de::from_slice(b"\x83\x01\x02\x03").unwrap();
+    assert_eq!(raw, RawValue::from_bytes(b"\x83\x01\x02\x03".to_vec()));
+    assert!(de::from_slice::<RawValue>(b"\x83\x01\x02").is_err());
+}
+
+// serde 0.7 has no flattened fields, a hand-written impl keeps the entries it does not know as
+// `Value`s and writes them back after the known ones.
+#[derive(Debug, PartialEq)]
+struct Profile {
+    name: String,
+    rest: BTreeMap<String, Value>,
+}
+
+impl Deserialize for Profile {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Profile, D::Error> {
+        struct ProfileVisitor;
+
+        impl Visitor for ProfileVisitor {
+            type Value = Profile;
+
+            fn visit_map<V: MapVisitor>(&mut self, mut visitor: V) -> Result<Profile, V::Error> {
+                let mut name = None;
+                let mut rest = BTreeMap::new();
+                while let Some(field) = try!(visitor.visit_key::<String>()) {
+                    if field == "name" {
+                        name = Some(try!(visitor.visit_value()));
+                    } else {
+                        rest.insert(field, try!(visitor.visit_value()));
+                    }
+                }
+                try!(visitor.end());
+                Ok(Profile {
+                    name: match name {
+                        Some(name) => name,
+                        None => try!(visitor.missing_field("name")),
+                    },
+                    rest: rest,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(ProfileVisitor)
+    }
+}
+
+impl serde::Serialize for Profile {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        let name = Value::String(self.name.clone());
+        let entries = Some(("name", &name)).into_iter()
+            .chain(self.rest.iter().map(|(key, value)| (&key[..], value)));
+        serializer.serialize_map(serde::ser::impls::MapIteratorVisitor::new(entries, Some(self.rest.len() + 1)))
+    }
+}
+
+#[test]
+fn test_unknown_fields_round_trip() {
+    let input = b"\xbf\x65extra\xc1\x1a\x00\x01\x00\x00\x64name\x63Ada\x64tags\x9f\x61x\xa1\x61y\xf6\xff\xff";
+    let profile: Profile = de::from_slice(input).unwrap();
+    assert_eq!(profile.name, "Ada");
+    assert_eq!(profile.rest.len(), 2);
+    assert_eq!(profile.rest["extra"], Value::Tag(1, Box::new(Value::U64(65536))));
+    let output = serde_cbor::to_vec(&profile).unwrap();
+    assert_eq!(de::from_slice::<Value>(&output).unwrap(), de::from_slice::<Value>(input).unwrap());
+    assert_eq!(de::from_slice::<Profile>(&output).unwrap(), profile);
+    let profile: Profile = de::from_slice(b"\xa1\x64name\x60").unwrap();
+    assert!(profile.rest.is_empty());
+}
+
+// serde 0.7 has no adjacently tagged enums, a hand-written impl buffers the content as `RawValue`
+// until it knows the variant from the tag.
+#[derive(Debug, PartialEq)]
+enum Message {
+    Ping,
+    Data(u32),
+}
+
+impl Deserialize for Message {
+    fn deserialize<D: Deserializer>(deserializer: &mut D) -> Result<Message, D::Error> {
+        struct MessageVisitor;
+
+        impl Visitor for MessageVisitor {
+            type Value = Message;
+
+            fn visit_map<V: MapVisitor>(&mut self, mut visitor: V) -> Result<Message, V::Error> {
+                let mut tag: Option<String> = None;
+                let mut content: Option<RawValue> = None;
+                while let Some(field) = try!(visitor.visit_key::<String>()) {
+                    match &field[..] {
+                        "t" => tag = Some(try!(visitor.visit_value())),
+                        "c" => content = Some(try!(visitor.visit_value())),
+                        _ => {
+                            try!(visitor.visit_value::<IgnoredAny>());
+                        }
+                    }
+                }
+                try!(visitor.end());
+                let content = content.map(|content| content.into_bytes());
+                match (tag.as_ref().map(|tag| &tag[..]), content) {
+                    (Some("Ping"), None) => Ok(Message::Ping),
+                    (Some("Data"), Some(content)) => match de::from_slice(&content) {
+                        Ok(data) => Ok(Message::Data(data)),
+                        Err(e) => Err(serde::de::Error::custom(e.to_string())),
+                    },
+                    (None, _) => visitor.missing_field("t"),
+                    _ => Err(serde::de::Error::invalid_value("unexpected content")),
+                }
+            }
+        }
+
+        deserializer.deserialize_map(MessageVisitor)
+    }
+}
+
+#[test]
+fn test_adjacently_tagged() {
+    assert_eq!(de::from_slice::<Message>(b"\xa2\x61t\x64Data\x61c\x05").unwrap(), Message::Data(5));
+    assert_eq!(de::from_slice::<Message>(b"\xa2\x61c\x05\x61t\x64Data").unwrap(), Message::Data(5));
+    assert_eq!(de::from_slice::<Message>(b"\xbf\x61c\x19\x01\x00\x61t\x64Data\xff").unwrap(),
+               Message::Data(256));
+    assert_eq!(de::from_slice::<Message>(b"\xa1\x61t\x64Ping").unwrap(), Message::Ping);
+    assert!(de::from_slice::<Message>(b"\xa2\x61c\x61x\x61t\x64Data").is_err());
+    assert!(de::from_slice::<Message>(b"\xa1\x61c\x05").is_err());
+}
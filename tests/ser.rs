@@ -1,9 +1,17 @@
 extern crate serde;
 extern crate serde_cbor;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::Duration;
 
-use serde_cbor::{to_vec, to_vec_sd, from_slice};
+use serde::Serialize;
+use serde::ser::impls::SeqIteratorVisitor;
+use serde_cbor::{to_vec, to_vec_sd, from_slice, Value};
+use serde_cbor::de::{self, StreamDeserializer};
+use serde_cbor::ser::{self, Serializer, IntegerWidth, Options, Width};
 
 #[test]
 fn test_string() {
@@ -84,3 +92,358 @@ fn test_self_describing() {
     let vec = to_vec_sd(&0).unwrap();
     assert_eq!(vec, b"\xd9\xd9\xf7\x00");
 }
+
+#[test]
+fn test_tagged_value() {
+    let value = Value::Tag(1, Box::new(Value::U64(1363896240)));
+    let vec = to_vec(&value).unwrap();
+    assert_eq!(vec, b"\xc1\x1a\x51\x4b\x67\xb0");
+    let value = Value::Array(vec![Value::Tag(32, Box::new(Value::String("a".to_owned()))), Value::Null]);
+    let vec = to_vec(&value).unwrap();
+    assert_eq!(vec, b"\x82\xd8\x20\x61\x61\xf6");
+    assert_eq!(from_slice::<Value>(&vec).unwrap(), value);
+}
+
+#[test]
+fn test_duration() {
+    use serde_cbor::de::Deserializer;
+    use serde_cbor::duration;
+
+    fn decode(bytes: &[u8]) -> serde_cbor::Result<Duration> {
+        duration::deserialize(&mut Deserializer::new(bytes))
+    }
+
+    let value = Duration::new(86400, 500_000_000);
+    let mut vec = Vec::new();
+    duration::serialize(&value, &mut Serializer::new(&mut vec)).unwrap();
+    assert_eq!(vec, b"\x82\x1a\x00\x01\x51\x80\x1a\x1d\xcd\x65\x00");
+    assert_eq!(decode(&vec).unwrap(), value);
+    let mut vec = Vec::new();
+    duration::serialize_f64(&value, &mut Serializer::new(&mut vec)).unwrap();
+    assert_eq!(vec, b"\xfa\x47\xa8\xc0\x40");
+    assert_eq!(decode(&vec).unwrap(), value);
+    assert_eq!(decode(b"\x18\x3c").unwrap(), Duration::new(60, 0));
+    assert_eq!(decode(b"\xd9\x03\xea\x82\x01\x02").unwrap(), Duration::new(1, 2));
+    assert!(decode(b"\x20").is_err());
+    assert!(decode(b"\xfa\xbf\x80\x00\x00").is_err());
+    assert!(decode(b"\xf9\x7e\x00").is_err());
+    assert!(decode(b"\x82\x01\x1a\x3b\x9a\xca\x00").is_err());
+}
+
+#[test]
+fn test_smallest_float() {
+    fn encode(v: f64) -> Vec<u8> {
+        let mut vec = Vec::new();
+        v.serialize(&mut Serializer::new(&mut vec).prefer_smallest_float()).unwrap();
+        vec
+    }
+
+    assert_eq!(encode(0.0), b"\xf9\x00\x00");
+    assert_eq!(encode(-0.0), b"\xf9\x80\x00");
+    assert_eq!(encode(1.0), b"\xf9\x3c\x00");
+    assert_eq!(encode(-2.5), b"\xf9\xc1\x00");
+    assert_eq!(encode(65504.0), b"\xf9\x7b\xff");
+    assert_eq!(encode(65505.0), b"\xfa\x47\x7f\xe1\x00");
+    assert_eq!(encode(0.00006103515625), b"\xf9\x04\x00");
+    assert_eq!(encode(0.000000059604644775390625), b"\xf9\x00\x01");
+    assert_eq!(encode(0.00000005960464477539063), b"\xf9\x00\x01");
+    assert_eq!(encode(0.0000000298023223876953125), b"\xfa\x33\x00\x00\x00");
+    assert_eq!(encode(0.00004673004150390625), b"\xf9\x03\x10");
+    assert_eq!(encode(1.0e-40), b"\xfb\x37\xa1\x6c\x26\x27\x77\x57\x9c");
+    assert_eq!(encode(100000.0), b"\xfa\x47\xc3\x50\x00");
+    assert_eq!(encode(1.1), b"\xfb\x3f\xf1\x99\x99\x99\x99\x99\x9a");
+    assert_eq!(encode(::std::f64::INFINITY), b"\xf9\x7c\x00");
+    assert_eq!(encode(::std::f64::NEG_INFINITY), b"\xf9\xfc\x00");
+    assert_eq!(encode(::std::f64::NAN), b"\xf9\x7e\x00");
+    for v in &[0.0, -0.0, 1.0, -2.5, 65504.0, 0.00006103515625, 0.000000059604644775390625, 1.0e-40] {
+        let decoded: f64 = from_slice(&encode(*v)).unwrap();
+        assert_eq!(decoded.to_bits(), v.to_bits());
+    }
+    assert_eq!(to_vec(&1.0f64).unwrap(), b"\xfa\x3f\x80\x00\x00");
+}
+
+#[test]
+fn test_integer_width() {
+    fn encode<T: Serialize>(v: T, width: Width) -> serde_cbor::Result<Vec<u8>> {
+        let mut vec = Vec::new();
+        try!(v.serialize(&mut Serializer::new(&mut vec).integer_width(IntegerWidth::Fixed(width))));
+        Ok(vec)
+    }
+
+    assert_eq!(encode(1u64, Width::Width8).unwrap(), b"\x1b\x00\x00\x00\x00\x00\x00\x00\x01");
+    assert_eq!(encode(1u8, Width::Width4).unwrap(), b"\x1a\x00\x00\x00\x01");
+    assert_eq!(encode(0u16, Width::Width1).unwrap(), b"\x18\x00");
+    assert_eq!(encode(-1i32, Width::Width2).unwrap(), b"\x39\x00\x00");
+    assert_eq!(encode(-500i64, Width::Width2).unwrap(), b"\x39\x01\xf3");
+    assert_eq!(encode(::std::i64::MIN, Width::Width8).unwrap(), b"\x3b\x7f\xff\xff\xff\xff\xff\xff\xff");
+    assert_eq!(encode(vec![1u8, 2], Width::Width2).unwrap(), b"\x82\x19\x00\x01\x19\x00\x02");
+    assert!(encode(256u64, Width::Width1).is_err());
+    assert!(encode(-65537i64, Width::Width2).is_err());
+    assert!(encode(1u64 << 32, Width::Width4).is_err());
+
+    let decoded: u64 = from_slice(&encode(300u64, Width::Width8).unwrap()).unwrap();
+    assert_eq!(decoded, 300);
+    assert_eq!(to_vec(&1u64).unwrap(), b"\x01");
+}
+
+#[test]
+fn test_char() {
+    fn encode_as_integer(c: char) -> Vec<u8> {
+        let mut vec = Vec::new();
+        c.serialize(&mut Serializer::new(&mut vec).chars_as_integers()).unwrap();
+        vec
+    }
+
+    assert_eq!(to_vec(&'A').unwrap(), b"\x61A");
+    assert_eq!(to_vec(&'🦀').unwrap(), b"\x64\xf0\x9f\xa6\x80");
+    assert_eq!(encode_as_integer('A'), b"\x18\x41");
+    assert_eq!(encode_as_integer('🦀'), b"\x1a\x00\x01\xf9\x80");
+
+    for &c in &['A', '🦀'] {
+        assert_eq!(from_slice::<char>(&to_vec(&c).unwrap()).unwrap(), c);
+        assert_eq!(from_slice::<char>(&encode_as_integer(c)).unwrap(), c);
+    }
+    assert!(from_slice::<char>(b"\x19\xd8\x00").is_err());
+    assert!(from_slice::<char>(b"\x1b\x00\x00\x00\x01\x00\x00\x00\x41").is_err());
+    assert!(from_slice::<char>(b"\x62AB").is_err());
+}
+
+#[test]
+fn test_trait_object() {
+    // serde's `Serialize` is not object safe, plugins erase it behind the concrete serializer.
+    trait Plugin {
+        fn encode(&self, ser: &mut Serializer<&mut Vec<u8>>) -> serde_cbor::Result<()>;
+    }
+
+    impl<T: serde::Serialize> Plugin for T {
+        fn encode(&self, ser: &mut Serializer<&mut Vec<u8>>) -> serde_cbor::Result<()> {
+            self.serialize(ser)
+        }
+    }
+
+    let plugins: Vec<Box<Plugin>> = vec![Box::new(1u8), Box::new("a"), Box::new(vec![true])];
+    let mut vec = Vec::new();
+    for plugin in &plugins {
+        let mut ser = Serializer::new(&mut vec);
+        plugin.encode(&mut ser).unwrap();
+    }
+    assert_eq!(vec, b"\x01aa\x81\xf5");
+}
+
+struct Unsized(Vec<u32>);
+
+impl serde::Serialize for Unsized {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_seq(SeqIteratorVisitor::new(self.0.iter(), None))
+    }
+}
+
+#[test]
+fn test_canonical() {
+    let mut object = HashMap::new();
+    object.insert("aa".to_owned(), Unsized(vec![1, 2]));
+    object.insert("b".to_owned(), Unsized(vec![]));
+    object.insert("a".to_owned(), Unsized(vec![3]));
+    let vec = ser::to_vec_canonical(&object).unwrap();
+    assert_eq!(vec, b"\xa3\x61a\x81\x03\x61b\x80\x62aa\x82\x01\x02");
+    assert_eq!(to_vec(&Unsized(vec![1, 2])).unwrap(), b"\x9f\x01\x02\xff");
+}
+
+fn to_vec_typed<T: serde::Serialize>(value: &T) -> Vec<u8> {
+    let mut vec = Vec::new();
+    value.serialize(&mut Serializer::new(&mut vec).typed_arrays()).unwrap();
+    vec
+}
+
+#[test]
+fn test_typed_arrays() {
+    let vec = to_vec_typed(&vec![1.5f32, -2.0]);
+    assert_eq!(vec, b"\xd8\x51\x48\x3f\xc0\x00\x00\xc0\x00\x00\x00");
+    assert_eq!(from_slice::<Vec<f32>>(&vec).unwrap(), vec![1.5, -2.0]);
+    let vec = to_vec_typed(&vec![1u16, 256]);
+    assert_eq!(vec, b"\xd8\x41\x44\x00\x01\x01\x00");
+    assert_eq!(from_slice::<Vec<u16>>(&vec).unwrap(), vec![1, 256]);
+    let vec = to_vec_typed(&vec![vec![-1i32], vec![]]);
+    assert_eq!(vec, b"\x82\xd8\x4a\x44\xff\xff\xff\xff\x80");
+    assert_eq!(from_slice::<Vec<Vec<i32>>>(&vec).unwrap(), vec![vec![-1], vec![]]);
+    let value = vec![0.1f64, ::std::f64::INFINITY];
+    assert_eq!(from_slice::<Vec<f64>>(&to_vec_typed(&value)).unwrap(), value);
+    let value = (vec![1u64, 2], Unsized(vec![3, 4]));
+    let vec = to_vec_typed(&value);
+    assert_eq!(vec, b"\x82\xd8\x43\x50\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x02\
+                      \xd8\x42\x48\x00\x00\x00\x03\x00\x00\x00\x04");
+    assert_eq!(to_vec_typed(&(1u8, 2u16)), b"\x82\x01\x02");
+    assert_eq!(to_vec_typed(&vec![Some(1u8), None]), b"\x82\x01\xf6");
+    assert_eq!(to_vec_typed(&vec!["a"]), b"\x81\x61a");
+
+    // Wrapped numbers do not decode from a typed array, they stay a plain array.
+    let vec = to_vec_typed(&vec![Some(1u8), Some(2u8)]);
+    assert_eq!(vec, b"\x82\x01\x02");
+    assert_eq!(from_slice::<Vec<Option<u8>>>(&vec).unwrap(), vec![Some(1), Some(2)]);
+
+    struct Meters(u32);
+
+    impl Serialize for Meters {
+        fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+            serializer.serialize_newtype_struct("Meters", self.0)
+        }
+    }
+
+    assert_eq!(to_vec_typed(&vec![Meters(1), Meters(2)]), b"\x82\x01\x02");
+}
+
+#[test]
+fn test_canonical_streaming() {
+    struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Checks how much has been written when it is serialized.
+    struct Written(Rc<RefCell<Vec<u8>>>, usize);
+
+    impl serde::Serialize for Written {
+        fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+            assert_eq!(self.0.borrow().len(), self.1);
+            serializer.serialize_unit()
+        }
+    }
+
+    let shared = Rc::new(RefCell::new(Vec::new()));
+    let mut object = HashMap::new();
+    object.insert("b", vec![1]);
+    object.insert("a", vec![2, 3]);
+    let value = (object, Written(shared.clone(), 11));
+    serde_cbor::ser::to_writer_canonical(&mut SharedWriter(shared.clone()), &value).unwrap();
+    assert_eq!(&shared.borrow()[..], b"\x82\xa2\x61a\x82\x02\x03\x61b\x81\x01\xf6");
+}
+
+#[test]
+fn test_sequence() {
+    let items = vec![Value::U64(1), Value::String("two".to_owned()), Value::Array(vec![Value::Null])];
+    let vec = ser::to_vec_seq(&items).unwrap();
+    assert_eq!(vec, b"\x01\x63two\x81\xf6");
+    let decoded = StreamDeserializer::new(de::Deserializer::new(&vec[..]))
+        .collect::<serde_cbor::Result<Vec<Value>>>()
+        .unwrap();
+    assert_eq!(decoded, items);
+
+    let mut writer = Vec::new();
+    ser::to_writer_seq(&mut writer, (0..3u8).map(|n| n * 100)).unwrap();
+    assert_eq!(writer, b"\x00\x18\x64\x18\xc8");
+    assert_eq!(ser::to_vec_seq(Vec::<u8>::new()).unwrap(), b"");
+
+    let mut stream = StreamDeserializer::<_, u8>::new(de::Deserializer::new(&b"\x01\x61a\x02"[..]));
+    assert_eq!(stream.next().unwrap().unwrap(), 1);
+    assert!(stream.next().unwrap().is_err());
+    assert!(stream.next().is_none());
+}
+
+#[test]
+fn test_map_iter() {
+    let keys = vec!["a".to_owned(), "b".to_owned()];
+    let values = vec![1u8, 2];
+    let vec = ser::to_vec_map_iter(keys.iter().zip(values.iter()), Some(2)).unwrap();
+    assert_eq!(vec, b"\xa2\x61a\x01\x61b\x02");
+    let vec = ser::to_vec_map_iter((0..3u8).map(|n| (n, n == 1)), None).unwrap();
+    assert_eq!(vec, b"\xbf\x00\xf4\x01\xf5\x02\xf4\xff");
+    let decoded: HashMap<u8, bool> = from_slice(&vec).unwrap();
+    assert_eq!(decoded.len(), 3);
+
+    let mut vec = Vec::new();
+    {
+        let mut serializer = Serializer::new(&mut vec).canonical();
+        ser::serialize_map_iter(&mut serializer, vec![(10u8, ()), (2, ())], None).unwrap();
+    }
+    assert_eq!(vec, b"\xa2\x02\xf6\x0a\xf6");
+    assert!(ser::to_vec_map_iter(vec![(1u8, 1u8)], Some(2)).is_err());
+
+    // A known mismatch is caught before the header, others leave a broken map behind.
+    let mut vec = Vec::new();
+    let pairs = vec![(1u8, 1u8)];
+    assert!(ser::serialize_map_iter(&mut Serializer::new(&mut vec), pairs, Some(2)).is_err());
+    assert_eq!(vec, b"");
+    let pairs = (0..3u8).filter(|&n| n != 1).map(|n| (n, n));
+    assert!(ser::serialize_map_iter(&mut Serializer::new(&mut vec), pairs, Some(3)).is_err());
+    assert_eq!(vec, b"\xa3\x00\x00\x02\x02");
+    assert!(from_slice::<HashMap<u8, u8>>(&vec).is_err());
+}
+
+#[test]
+fn test_preamble() {
+    let mut vec = Vec::new();
+    {
+        let mut serializer = Serializer::new(&mut vec).with_preamble(b"MYFMT\x01").unwrap();
+        vec![1u8, 2].serialize(&mut serializer).unwrap();
+    }
+    assert_eq!(vec, b"MYFMT\x01\x82\x01\x02");
+    let value: Vec<u8> = de::from_reader_with_preamble(&vec[..], b"MYFMT\x01").unwrap();
+    assert_eq!(value, vec![1, 2]);
+    for magic in &[&b"MYFMT\x02"[..], b"YFMT\x01"] {
+        match de::from_reader_with_preamble::<Vec<u8>, _>(&vec[..], magic) {
+            Err(serde_cbor::Error::BadMagic) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+    match de::from_reader_with_preamble::<Vec<u8>, _>(&b"MY"[..], b"MYFMT\x01") {
+        Err(serde_cbor::Error::BadMagic) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_encode_with() {
+    let input = b"\xbf\x62bb\x9f\x19\x00\x01\xfb\x3f\xf8\x00\x00\x00\x00\x00\x00\xff\x61a\xc1\x18\x64\xff";
+    let value: Value = from_slice(input).unwrap();
+
+    let canonical = value.encode_with(Options::new().canonical().prefer_smallest_float()).unwrap();
+    assert_eq!(canonical, b"\xa2\x61a\xc1\x18\x64\x62bb\x82\x01\xf9\x3e\x00");
+    assert_eq!(from_slice::<Value>(&canonical).unwrap(), value);
+    assert!(de::from_slice_checked::<Value>(&canonical).unwrap().1);
+
+    let compact = value.encode_with(Options::new()).unwrap();
+    assert_eq!(compact.len(), 16);
+    assert_eq!(from_slice::<Value>(&compact).unwrap(), value);
+
+    let wide = value.encode_with(Options::new().canonical().integer_width(IntegerWidth::Fixed(Width::Width2)))
+        .unwrap();
+    assert_eq!(wide, b"\xa2\x61a\xc1\x19\x00\x64\x62bb\x82\x19\x00\x01\xfa\x3f\xc0\x00\x00");
+    assert_eq!(Options::default(), Options::new());
+}
+
+#[test]
+fn test_hashed() {
+    // FNV-1a, standing in for a cryptographic digest.
+    struct Fnv(u64);
+
+    impl Write for Fnv {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut object = HashMap::new();
+    for i in 0..20u8 {
+        object.insert(i.to_string(), vec![i; i as usize]);
+    }
+    let (vec, hasher) = serde_cbor::ser::to_vec_hashed(&object, Fnv(0xcbf29ce484222325)).unwrap();
+    let expected = ser::to_vec_canonical(&object).unwrap();
+    let mut expected_hasher = Fnv(0xcbf29ce484222325);
+    expected_hasher.write_all(&expected).unwrap();
+    assert_eq!(vec, expected);
+    assert_eq!(hasher.0, expected_hasher.0);
+}